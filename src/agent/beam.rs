@@ -0,0 +1,113 @@
+use crate::*;
+
+/// Searches through the preview and the hold slot, keeping only the `width`
+/// most promising boards after each piece. Cheaper than a full search at the
+/// same depth, at the risk of pruning the line that would have paid off.
+#[derive(Debug, Clone)]
+pub struct BeamAgent {
+    pub evaluator: GeneticAgent,
+    // Boards kept after each ply
+    pub width: usize,
+    // Number of pieces to plan for, including the current one
+    pub depth: usize,
+}
+
+// A line of play, remembered by where it started
+struct Node {
+    first: Placement,
+    loss: f64,
+    // State after the line, unless it cannot be followed any further
+    state: Option<TetrisState>,
+}
+
+impl BeamAgent {
+    pub fn new(evaluator: GeneticAgent, width: usize, depth: usize) -> BeamAgent {
+        BeamAgent {
+            evaluator,
+            width: width.max(1),
+            depth: depth.max(1),
+        }
+    }
+
+    // Every placement in `state`, each extending the line that began with
+    // `first`, or starting a new one. Unless this is the `last` piece of the
+    // search, lines that cannot be followed any further are lost.
+    fn expand(
+        &self,
+        state: &TetrisState,
+        first: Option<&Placement>,
+        last: bool,
+        nodes: &mut Vec<Node>,
+    ) {
+        for placement in state.placements() {
+            let (board, info) = state.resolve_placement(&placement);
+            let loss = self.evaluator.loss_function(&board, &info);
+            let next = state.after_placement(&placement);
+
+            let (loss, next) = match next {
+                Some(next) if next.is_over => (f64::INFINITY, None),
+                // Too few pieces left in the preview to follow this line as
+                // far as the others
+                None if !last => (f64::INFINITY, None),
+                next => (loss, next),
+            };
+
+            nodes.push(Node {
+                first: first.unwrap_or(&placement).clone(),
+                loss,
+                state: next,
+            });
+        }
+    }
+
+    // Every line is followed to the same depth, which the preview caps
+    fn search(&self, state: &TetrisState) -> Option<Placement> {
+        let depth = self.depth.clamp(1, state.queue.len() + 1);
+        let mut beam = Vec::new();
+        self.expand(state, None, depth == 1, &mut beam);
+
+        for ply in 2..=depth {
+            beam.sort_by(|a, b| a.loss.partial_cmp(&b.loss).unwrap());
+            beam.truncate(self.width);
+
+            // Lost lines stay lost
+            let mut next = Vec::with_capacity(beam.len());
+            for node in beam {
+                match &node.state {
+                    Some(state) => self.expand(state, Some(&node.first), ply == depth, &mut next),
+                    None => next.push(node),
+                }
+            }
+            beam = next;
+        }
+
+        beam.into_iter()
+            .min_by(|a, b| a.loss.partial_cmp(&b.loss).unwrap())
+            .map(|node| node.first)
+    }
+}
+
+impl Agent for BeamAgent {
+    fn get_action(&mut self, state: &TetrisState) -> Option<Action> {
+        self.search(state).map(Action::Place)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_scored_to_the_same_depth() {
+        // With one piece in the preview and nothing held, holding uses up
+        // the preview and cannot be followed to a second piece, so it must
+        // not be compared with lines that were
+        let agent = BeamAgent::new(GeneticAgent::dellacherie(), 8, 3);
+
+        for seed in 0..40 {
+            let state = TetrisState::new(Box::new(Bag::new(1)), 1, seed);
+            let placement = agent.search(&state).unwrap();
+            assert!(!placement.hold, "Held on seed {}", seed);
+        }
+    }
+}
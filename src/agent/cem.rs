@@ -0,0 +1,94 @@
+use crate::*;
+
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+/// Hyperparameters of `CrossEntropy`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CemConfig {
+    pub num_generations: usize,
+    pub population_size: usize,
+    // Fraction of each generation that the distribution is refitted to
+    pub elite_fraction: f64,
+    // Initial standard deviation of every weight, around a mean of zero
+    pub step_size: f64,
+    // Variance added to every weight after refitting, which keeps the search
+    // from collapsing early. Reduced by `noise_decay` each generation.
+    pub noise: f64,
+    pub noise_decay: f64,
+}
+
+/// Noisy cross-entropy method of Szita and Lőrincz: parameters are drawn
+/// from independent Gaussians, which are refitted to the fittest samples
+/// every generation. Recommends an individual with the mean parameters.
+#[derive(Serialize, Deserialize)]
+pub struct CrossEntropy<G> {
+    config: CemConfig,
+    template: G,
+    mean: Vec<f64>,
+    variance: Vec<f64>,
+    generation: usize,
+}
+
+impl<G: Genome> CrossEntropy<G> {
+    pub fn new(template: &G, config: CemConfig) -> CrossEntropy<G> {
+        let n = template.parameters().len();
+
+        CrossEntropy {
+            config,
+            template: template.clone(),
+            mean: vec![0.0; n],
+            variance: vec![config.step_size.powi(2); n],
+            generation: 0,
+        }
+    }
+}
+
+impl<G: Genome> Optimizer<G> for CrossEntropy<G> {
+    fn ask(&mut self, rng: &mut SmallRng) -> Vec<G> {
+        (0..self.config.population_size)
+            .map(|_| {
+                let parameters = self
+                    .mean
+                    .iter()
+                    .zip(&self.variance)
+                    .map(|(m, v)| {
+                        let r: f64 = rng.sample(StandardNormal);
+                        m + v.sqrt() * r
+                    })
+                    .collect();
+                self.template.with_parameters(parameters)
+            })
+            .collect()
+    }
+
+    fn tell(&mut self, population: Vec<G>, fitness_values: &[f64], _rng: &mut SmallRng) {
+        let config = &self.config;
+        let elites = ((population.len() as f64 * config.elite_fraction).ceil() as usize)
+            .max(1)
+            .min(population.len());
+
+        let ranking = by_fitness(fitness_values);
+        let elite = ranking[..elites]
+            .iter()
+            .map(|&i| population[i].parameters())
+            .collect::<Vec<_>>();
+
+        let noise = (config.noise - config.noise_decay * self.generation as f64).max(0.0);
+        for i in 0..self.mean.len() {
+            self.mean[i] = elite.iter().map(|w| w[i]).sum::<f64>() / elites as f64;
+            self.variance[i] = elite
+                .iter()
+                .map(|w| (w[i] - self.mean[i]).powi(2))
+                .sum::<f64>()
+                / elites as f64
+                + noise;
+        }
+
+        self.generation += 1;
+    }
+
+    fn recommend(&self) -> Option<G> {
+        Some(self.template.with_parameters(self.mean.clone()))
+    }
+}
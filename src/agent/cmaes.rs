@@ -0,0 +1,342 @@
+// Vector and matrix updates read most naturally as index loops
+#![allow(clippy::needless_range_loop)]
+
+use crate::*;
+
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+/// Hyperparameters of `CmaEs`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CmaesConfig {
+    // Generations in total, across all restarts
+    pub num_generations: usize,
+    // Offspring per generation, zero for the default of 4 + 3 ln(n). Doubled
+    // at every restart, as in IPOP-CMA-ES.
+    pub population_size: usize,
+    // Initial standard deviation of the search around the mean
+    pub step_size: f64,
+    pub max_restarts: usize,
+    // Generations without a new best individual before restarting
+    pub stagnation: usize,
+}
+
+// Restart once the search has shrunk to this fraction of its initial size
+const TOL_X: f64 = 1e-6;
+// Restart once the covariance matrix is this badly conditioned
+const MAX_CONDITION: f64 = 1e14;
+
+// State of a single CMA-ES run. Follows "The CMA Evolution Strategy: A
+// Tutorial" by Nikolaus Hansen, maximising fitness.
+#[derive(Serialize, Deserialize)]
+struct Cmaes {
+    n: usize,
+    lambda: usize,
+    // Recombination weights of the best `weights.len()` offspring
+    weights: Vec<f64>,
+    mueff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+
+    mean: Vec<f64>,
+    sigma: f64,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    c: Vec<Vec<f64>>,
+    // Eigendecomposition of `c`: eigenvectors as columns of `b`, and the
+    // square roots of the eigenvalues in `d`
+    b: Vec<Vec<f64>>,
+    d: Vec<f64>,
+    generation: usize,
+}
+
+impl Cmaes {
+    fn new(mean: Vec<f64>, sigma: f64, lambda: usize) -> Cmaes {
+        let n = mean.len();
+        let nf = n as f64;
+        let mu = lambda / 2;
+
+        let mut weights = (0..mu)
+            .map(|i| (mu as f64 + 0.5).ln() - (i as f64 + 1.0).ln())
+            .collect::<Vec<f64>>();
+        let sum = weights.iter().sum::<f64>();
+        weights.iter_mut().for_each(|w| *w /= sum);
+        let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let cc = (4.0 + mueff / nf) / (nf + 4.0 + 2.0 * mueff / nf);
+        let cs = (mueff + 2.0) / (nf + mueff + 5.0);
+        let c1 = 2.0 / ((nf + 1.3).powi(2) + mueff);
+        let cmu = (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) / ((nf + 2.0).powi(2) + mueff));
+        let damps = 1.0 + 2.0 * (((mueff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+
+        Cmaes {
+            n,
+            lambda,
+            weights,
+            mueff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n,
+            mean,
+            sigma,
+            pc: vec![0.0; n],
+            ps: vec![0.0; n],
+            c: identity(n),
+            b: identity(n),
+            d: vec![1.0; n],
+            generation: 0,
+        }
+    }
+
+    // Offspring for the next generation, as weight vectors
+    fn sample(&self, rng: &mut SmallRng) -> Vec<Vec<f64>> {
+        (0..self.lambda)
+            .map(|_| {
+                let z = (0..self.n)
+                    .map(|i| self.d[i] * rng.sample::<f64, _>(StandardNormal))
+                    .collect::<Vec<f64>>();
+                let y = mul(&self.b, &z);
+                (0..self.n)
+                    .map(|i| self.mean[i] + self.sigma * y[i])
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Moves the distribution towards the best offspring, given fittest first
+    fn update(&mut self, ranked: &[&Vec<f64>]) {
+        let n = self.n;
+        let old_mean = self.mean.clone();
+
+        self.mean = vec![0.0; n];
+        for (w, x) in self.weights.iter().zip(ranked) {
+            for i in 0..n {
+                self.mean[i] += w * x[i];
+            }
+        }
+
+        let y_w = (0..n)
+            .map(|i| (self.mean[i] - old_mean[i]) / self.sigma)
+            .collect::<Vec<f64>>();
+
+        // C^(-1/2) y_w = B D^-1 B^T y_w
+        let bt_y = mul(&transpose(&self.b), &y_w);
+        let scaled = (0..n).map(|i| bt_y[i] / self.d[i]).collect::<Vec<f64>>();
+        let c_inv_sqrt_y = mul(&self.b, &scaled);
+
+        let cs_factor = (self.cs * (2.0 - self.cs) * self.mueff).sqrt();
+        for i in 0..n {
+            self.ps[i] = (1.0 - self.cs) * self.ps[i] + cs_factor * c_inv_sqrt_y[i];
+        }
+
+        self.generation += 1;
+        let ps_norm = norm(&self.ps);
+        let decay = 1.0 - (1.0 - self.cs).powi(2 * self.generation as i32);
+        let hsig = ps_norm / decay.sqrt() / self.chi_n < 1.4 + 2.0 / (n as f64 + 1.0);
+        let hsig = if hsig { 1.0 } else { 0.0 };
+
+        let cc_factor = (self.cc * (2.0 - self.cc) * self.mueff).sqrt();
+        for i in 0..n {
+            self.pc[i] = (1.0 - self.cc) * self.pc[i] + hsig * cc_factor * y_w[i];
+        }
+
+        let ys = ranked
+            .iter()
+            .take(self.weights.len())
+            .map(|x| {
+                (0..n)
+                    .map(|i| (x[i] - old_mean[i]) / self.sigma)
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<_>>();
+
+        let correction = (1.0 - hsig) * self.cc * (2.0 - self.cc);
+        for i in 0..n {
+            for j in 0..n {
+                let rank_one = self.pc[i] * self.pc[j] + correction * self.c[i][j];
+                let rank_mu = self
+                    .weights
+                    .iter()
+                    .zip(&ys)
+                    .map(|(w, y)| w * y[i] * y[j])
+                    .sum::<f64>();
+                self.c[i][j] = (1.0 - self.c1 - self.cmu) * self.c[i][j]
+                    + self.c1 * rank_one
+                    + self.cmu * rank_mu;
+            }
+        }
+
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).exp();
+
+        let (eigenvalues, eigenvectors) = symmetric_eigen(&self.c);
+        self.d = eigenvalues.iter().map(|&e| e.max(1e-20).sqrt()).collect();
+        self.b = eigenvectors;
+    }
+
+    fn condition(&self) -> f64 {
+        let max = self.d.iter().cloned().fold(0.0, f64::max);
+        let min = self.d.iter().cloned().fold(f64::INFINITY, f64::min);
+        (max / min).powi(2)
+    }
+
+    // Largest standard deviation of the search along any direction
+    fn spread(&self) -> f64 {
+        self.sigma * self.d.iter().cloned().fold(0.0, f64::max)
+    }
+}
+
+/// CMA-ES, restarting with a larger population from a fresh random mean
+/// whenever the search stalls.
+#[derive(Serialize, Deserialize)]
+pub struct CmaEs<G> {
+    config: CmaesConfig,
+    template: G,
+    cmaes: Cmaes,
+    restarts: usize,
+    generation: usize,
+    best_score: Option<f64>,
+    last_improvement: usize,
+}
+
+impl<G: Genome> CmaEs<G> {
+    /// Starts the search around a random individual like `template`.
+    pub fn new(template: &G, config: CmaesConfig, rng: &mut SmallRng) -> CmaEs<G> {
+        let mean = template.random(rng).parameters();
+        let lambda = match config.population_size {
+            0 => 4 + (3.0 * (mean.len() as f64).ln()) as usize,
+            size => size.max(2),
+        };
+
+        CmaEs {
+            config,
+            template: template.clone(),
+            cmaes: Cmaes::new(mean, config.step_size, lambda),
+            restarts: 0,
+            generation: 0,
+            best_score: None,
+            last_improvement: 0,
+        }
+    }
+}
+
+impl<G: Genome> Optimizer<G> for CmaEs<G> {
+    fn ask(&mut self, rng: &mut SmallRng) -> Vec<G> {
+        self.cmaes
+            .sample(rng)
+            .into_iter()
+            .map(|parameters| self.template.with_parameters(parameters))
+            .collect()
+    }
+
+    fn tell(&mut self, population: Vec<G>, fitness_values: &[f64], rng: &mut SmallRng) {
+        let ranking = by_fitness(fitness_values);
+
+        let score = fitness_values[ranking[0]];
+        if self.best_score.is_none_or(|best| score > best) {
+            self.best_score = Some(score);
+            self.last_improvement = self.generation;
+        }
+
+        let offspring = population
+            .iter()
+            .map(|individual| individual.parameters())
+            .collect::<Vec<Vec<f64>>>();
+        let ranked = ranking.iter().map(|&i| &offspring[i]).collect::<Vec<_>>();
+        self.cmaes.update(&ranked);
+
+        let stalled = self.cmaes.spread() < TOL_X * self.config.step_size
+            || self.cmaes.condition() > MAX_CONDITION
+            || self.generation - self.last_improvement >= self.config.stagnation;
+
+        if stalled && self.restarts < self.config.max_restarts {
+            self.restarts += 1;
+            self.last_improvement = self.generation;
+            let lambda = self.cmaes.lambda * 2;
+            println!("Restart {} with population size {}", self.restarts, lambda);
+
+            let mean = self.template.random(rng).parameters();
+            self.cmaes = Cmaes::new(mean, self.config.step_size, lambda);
+        }
+
+        self.generation += 1;
+    }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+fn transpose(a: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    (0..a.len())
+        .map(|j| a.iter().map(|row| row[j]).collect())
+        .collect()
+}
+
+fn mul(a: &[Vec<f64>], x: &[f64]) -> Vec<f64> {
+    a.iter()
+        .map(|row| row.iter().zip(x).map(|(a, x)| a * x).sum())
+        .collect()
+}
+
+fn norm(x: &[f64]) -> f64 {
+    x.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, using
+// cyclic Jacobi rotations. Plenty fast for a handful of weights.
+fn symmetric_eigen(a: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = a.len();
+    let mut a = a.to_vec();
+    let mut v = identity(n);
+
+    for _ in 0..100 {
+        let off_diagonal = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum::<f64>();
+        if off_diagonal < 1e-30 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for k in 0..n {
+                    let (akp, akq) = (a[k][p], a[k][q]);
+                    a[k][p] = c * akp - s * akq;
+                    a[k][q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p][k], a[q][k]);
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k][p], v[k][q]);
+                    v[k][p] = c * vkp - s * vkq;
+                    v[k][q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
@@ -0,0 +1,180 @@
+use crate::*;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How the scores of an individual's games are combined into its fitness.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Aggregation {
+    Mean,
+    Median,
+    Min,
+    // Mean after dropping this fraction of the games from either end
+    TrimmedMean(f64),
+    // Mean minus this many standard errors
+    LowerConfidenceBound(f64),
+    // Mean of this fraction of the games, taking the worst ones
+    CVaR(f64),
+}
+
+impl Aggregation {
+    pub fn aggregate(&self, scores: &[f64]) -> f64 {
+        let n = scores.len();
+        if n == 0 {
+            return 0.0;
+        }
+
+        let mut sorted = scores.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mean = |scores: &[f64]| scores.iter().sum::<f64>() / scores.len() as f64;
+
+        match *self {
+            Aggregation::Mean => mean(&sorted),
+            Aggregation::Median if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+            Aggregation::Median => sorted[n / 2],
+            Aggregation::Min => sorted[0],
+            Aggregation::TrimmedMean(fraction) => {
+                // Always keep at least one game
+                let k = ((n as f64 * fraction) as usize).min((n - 1) / 2);
+                mean(&sorted[k..n - k])
+            }
+            Aggregation::LowerConfidenceBound(z) => {
+                let mu = mean(&sorted);
+                if n < 2 {
+                    return mu;
+                }
+                let variance =
+                    sorted.iter().map(|x| (x - mu).powi(2)).sum::<f64>() / (n - 1) as f64;
+                mu - z * (variance / n as f64).sqrt()
+            }
+            Aggregation::CVaR(alpha) => {
+                let k = ((n as f64 * alpha).ceil() as usize).max(1).min(n);
+                mean(&sorted[..k])
+            }
+        }
+    }
+}
+
+/// Parses `mean`, `median`, `min`, `trimmed-mean:FRACTION`,
+/// `lower-confidence-bound:Z` or `cvar:ALPHA`.
+pub fn parse_aggregation(spec: &str) -> Option<Aggregation> {
+    let spec = spec.to_lowercase();
+    match spec.split_once(':') {
+        None => match spec.as_str() {
+            "mean" => Some(Aggregation::Mean),
+            "median" => Some(Aggregation::Median),
+            "min" => Some(Aggregation::Min),
+            _ => None,
+        },
+        Some(("trimmed-mean", fraction)) => fraction
+            .parse()
+            .ok()
+            .filter(|f| (0.0..0.5).contains(f))
+            .map(Aggregation::TrimmedMean),
+        Some(("lower-confidence-bound", z)) => {
+            z.parse().ok().map(Aggregation::LowerConfidenceBound)
+        }
+        Some(("cvar", alpha)) => alpha
+            .parse()
+            .ok()
+            .filter(|a| *a > 0.0 && *a <= 1.0)
+            .map(Aggregation::CVaR),
+        _ => None,
+    }
+}
+
+/// How individuals are scored while training.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EvalConfig {
+    // Games played by each individual
+    pub iterations: usize,
+    // Every individual of a generation plays the same piece sequences, so
+    // that differences in fitness come from the agents rather than from luck
+    pub common_seeds: bool,
+    pub aggregation: Aggregation,
+    // Games are cut short after this many pieces, strong agents would
+    // otherwise play practically forever
+    pub max_pieces: Option<usize>,
+    // Games are also cut short after this long
+    pub time_limit: Option<Duration>,
+}
+
+impl EvalConfig {
+    /// Seeds for the games of `n` individuals, one individual after the
+    /// other. Common seeds are drawn once and shared by everyone.
+    pub fn draw_seeds(&self, n: usize, rng: &mut SmallRng) -> Vec<u64> {
+        if self.common_seeds {
+            let seeds = (0..self.iterations)
+                .map(|_| rng.gen())
+                .collect::<Vec<u64>>();
+            seeds.repeat(n)
+        } else {
+            (0..n * self.iterations).map(|_| rng.gen()).collect()
+        }
+    }
+}
+
+/// Fitness of every agent in a population, evaluated in parallel with a copy
+/// of `state` per thread. Seeds are drawn from `rng` up front, so the result
+/// does not depend on how the work is split between threads.
+pub fn evaluate_population<A: Agent + Send>(
+    population: &mut [A],
+    state: &TetrisState,
+    eval: &EvalConfig,
+    rng: &mut SmallRng,
+) -> Vec<f64> {
+    let seeds = eval.draw_seeds(population.len(), rng);
+    let mut fitness_values = vec![0.0; population.len()];
+
+    population
+        .par_iter_mut()
+        .zip(fitness_values.par_iter_mut())
+        .zip(seeds.par_chunks(eval.iterations))
+        .for_each_init(
+            || state.clone(),
+            |state, ((agent, fitness), seeds)| {
+                *fitness = agent.evaluate_seeds(state, eval, seeds);
+            },
+        );
+
+    fitness_values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregations_on_fixed_scores() {
+        let scores = [4.0, 1.0, 3.0, 2.0, 10.0];
+        // Standard error of the mean of the scores above
+        let se = 2.5f64.sqrt();
+
+        let cases = [
+            (Aggregation::Mean, 4.0),
+            (Aggregation::Median, 3.0),
+            (Aggregation::Min, 1.0),
+            (Aggregation::TrimmedMean(0.0), 4.0),
+            (Aggregation::TrimmedMean(0.2), 3.0),
+            (Aggregation::TrimmedMean(0.49), 3.0),
+            (Aggregation::LowerConfidenceBound(0.0), 4.0),
+            (Aggregation::LowerConfidenceBound(2.0), 4.0 - 2.0 * se),
+            (Aggregation::CVaR(0.01), 1.0),
+            (Aggregation::CVaR(0.3), 1.5),
+            (Aggregation::CVaR(0.4), 1.5),
+            (Aggregation::CVaR(1.0), 4.0),
+        ];
+        for (aggregation, expected) in cases {
+            let fitness = aggregation.aggregate(&scores);
+            assert!((fitness - expected).abs() < 1e-9, "{:?}", aggregation);
+        }
+
+        // A single game has no spread to subtract
+        assert_eq!(
+            Aggregation::LowerConfidenceBound(2.0).aggregate(&[7.0]),
+            7.0
+        );
+        assert_eq!(Aggregation::TrimmedMean(0.4).aggregate(&[7.0]), 7.0);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::*;
+
+/// Plans past the end of the preview by averaging over every piece that could
+/// come next, weighted by the randomizer's distribution. Boards at the end of
+/// each line of play are scored with the weights of a `GeneticAgent`.
+#[derive(Debug, Clone)]
+pub struct ExpectimaxAgent {
+    pub evaluator: GeneticAgent,
+    // Number of pieces to plan for, including the current one
+    pub depth: usize,
+    // Placements expanded per node, keeping those with the lowest immediate
+    // loss. Zero expands every placement.
+    pub width: usize,
+    // Unseen pieces less likely than this are left out of the average
+    pub min_probability: f64,
+}
+
+impl ExpectimaxAgent {
+    pub fn new(
+        evaluator: GeneticAgent,
+        depth: usize,
+        width: usize,
+        min_probability: f64,
+    ) -> ExpectimaxAgent {
+        ExpectimaxAgent {
+            evaluator,
+            depth: depth.max(1),
+            width,
+            min_probability,
+        }
+    }
+
+    fn leaf_loss(&self, state: &TetrisState, placement: &Placement) -> f64 {
+        let (board, info) = state.resolve_placement(placement);
+        self.evaluator.loss_function(&board, &info)
+    }
+
+    // Placements worth expanding, along with their immediate loss
+    fn candidates(&self, state: &TetrisState) -> Vec<(Placement, f64)> {
+        let mut candidates = state
+            .placements()
+            .into_iter()
+            .map(|placement| {
+                let loss = self.leaf_loss(state, &placement);
+                (placement, loss)
+            })
+            .collect::<Vec<_>>();
+
+        if self.width > 0 && candidates.len() > self.width {
+            candidates.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+            candidates.truncate(self.width);
+        }
+
+        candidates
+    }
+
+    // Best placement in `state` and its expected loss
+    fn search(&self, state: &TetrisState, depth: usize) -> (f64, Option<Placement>) {
+        let mut best = (f64::INFINITY, None);
+
+        for (placement, loss) in self.candidates(state) {
+            let loss = self.expected_loss(state, &placement, loss, depth);
+            if best.1.is_none() || loss < best.0 {
+                best = (loss, Some(placement));
+            }
+        }
+
+        best
+    }
+
+    fn expected_loss(
+        &self,
+        state: &TetrisState,
+        placement: &Placement,
+        leaf_loss: f64,
+        depth: usize,
+    ) -> f64 {
+        if depth <= 1 {
+            return leaf_loss;
+        }
+
+        let value = |next: Option<TetrisState>| match next {
+            Some(next) if next.is_over => f64::INFINITY,
+            Some(next) => self.search(&next, depth - 1).0,
+            None => leaf_loss,
+        };
+
+        // Pieces in the preview are known, no need to average over them
+        if let Some(next) = state.after_placement(placement) {
+            return value(Some(next));
+        }
+
+        let distribution = state.randomizer.distribution();
+        let mut total = 0.0;
+        let mut total_probability = 0.0;
+
+        for &kind in &PIECE_KINDS {
+            let p = distribution[kind as usize];
+            if p <= 0.0 || p < self.min_probability {
+                continue;
+            }
+
+            let next = state.with_next_piece(kind).after_placement(placement);
+            total += p * value(next);
+            total_probability += p;
+        }
+
+        if total_probability > 0.0 {
+            total / total_probability
+        } else {
+            leaf_loss
+        }
+    }
+}
+
+impl Agent for ExpectimaxAgent {
+    fn get_action(&mut self, state: &TetrisState) -> Option<Action> {
+        self.search(state, self.depth).1.map(Action::Place)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(max_pieces: usize) -> EvalConfig {
+        EvalConfig {
+            iterations: 1,
+            common_seeds: false,
+            aggregation: Aggregation::Mean,
+            max_pieces: Some(max_pieces),
+            time_limit: None,
+        }
+    }
+
+    #[test]
+    fn plays_past_the_preview() {
+        // With no preview, every piece after the current one is unseen
+        let mut state = TetrisState::new(Box::new(Bag::new(1)), 0, 0);
+        let mut agent = ExpectimaxAgent::new(GeneticAgent::dellacherie(), 2, 6, 0.0);
+
+        agent.play_limited(&mut state, &eval(50));
+        assert!(!state.is_over);
+        assert_eq!(state.pieces, 50);
+    }
+
+    #[test]
+    fn certain_pieces_count_as_seen() {
+        // The piece after the current T is always an S, whether it is shown
+        // in the preview or not
+        let randomizer = parse_randomizer("fixed:TS").unwrap();
+        let mut unseen = TetrisState::new(randomizer.clone(), 0, 0);
+        let mut seen = TetrisState::new(randomizer, 1, 0);
+        // Holding is only possible when the next piece is shown
+        unseen.can_hold = false;
+        seen.can_hold = false;
+
+        let agent = ExpectimaxAgent::new(GeneticAgent::dellacherie(), 2, 0, 0.0);
+        assert_eq!(agent.search(&unseen, 2), agent.search(&seen, 2));
+    }
+}
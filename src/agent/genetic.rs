@@ -1,181 +1,165 @@
 use crate::*;
 
 use rand::rngs::SmallRng;
-use rand_distr::StandardNormal;
-
-// Somewhat arbitrarily chosen
-const STD_DEV: f64 = 100.0;
-
-fn sample_std(rng: &mut SmallRng) -> f64 {
-    let r: f64 = rng.sample(StandardNormal);
-    r * STD_DEV
+use serde::{Deserialize, Serialize};
+
+/// Hyperparameters of `GeneticAlgorithm`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GeneticConfig {
+    pub num_generations: usize,
+    pub population_size: usize,
+    // Parents picked each generation, which children are bred from
+    pub selection_size: usize,
+    pub selection: Selection,
+    // Fittest individuals copied unchanged into the next generation
+    pub elites: usize,
+    pub crossover: Crossover,
+    pub mutation: Mutation,
+    // Chance of a child being mutated at all
+    pub mutation_probability: f64,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeneticAgent {
-    pub weights: [f64; N_HEURISTICS],
+    pub features: FeatureSet,
+    // One weight per feature, in the same order
+    pub weights: Vec<f64>,
+    // Number of pieces to plan ahead for, including the current one
+    pub depth: usize,
+    // Standard deviation used by self-adaptive Gaussian mutation, which
+    // evolves along with the weights
+    pub step_size: f64,
 }
 
 impl GeneticAgent {
     // Initialize agent with random weights from a normal distribution [µ=0, σ=50]
-    pub fn new() -> GeneticAgent {
-        let mut weights: [f64; N_HEURISTICS] = [0.0; N_HEURISTICS];
-        let mut rng = SmallRng::from_entropy();
+    pub fn new(features: &FeatureSet, rng: &mut SmallRng) -> GeneticAgent {
+        let weights = (0..features.len()).map(|_| sample_std(rng)).collect();
+        GeneticAgent::with_weights(features.clone(), weights)
+    }
 
-        for w in weights.iter_mut() {
-            *w = sample_std(&mut rng);
+    pub fn with_weights(features: FeatureSet, weights: Vec<f64>) -> GeneticAgent {
+        assert_eq!(
+            features.len(),
+            weights.len(),
+            "Expected one weight per feature"
+        );
+
+        GeneticAgent {
+            features,
+            weights,
+            depth: 1,
+            step_size: 1.0,
         }
+    }
 
-        GeneticAgent { weights }
+    /// Searches `depth` pieces ahead when picking placements. Pieces beyond
+    /// the preview are never considered, whatever the depth.
+    pub fn with_depth(mut self, depth: usize) -> GeneticAgent {
+        self.depth = depth.max(1);
+        self
     }
 
-    pub fn breed(&self, other: &GeneticAgent, p: f64) -> GeneticAgent {
-        let mut weights = [0.0; N_HEURISTICS];
-        let mut rng = SmallRng::from_entropy();
+    pub fn loss_function(&self, board: &TetrisBoard, info: &PlacementInfo) -> f64 {
+        self.features.dot(&self.weights, board, info)
+    }
+}
 
-        for i in 0..N_HEURISTICS {
-            let r = rng.gen_bool(p);
-            weights[i] = if r { self.weights[i] } else { other.weights[i] };
-        }
+impl Agent for GeneticAgent {
+    fn get_action(&mut self, state: &TetrisState) -> Option<Action> {
+        state
+            .pick_placement(self.depth, |board, info| self.loss_function(board, info))
+            .map(Action::Place)
+    }
+}
 
-        GeneticAgent { weights }
+impl Genome for GeneticAgent {
+    fn random(&self, rng: &mut SmallRng) -> GeneticAgent {
+        GeneticAgent::new(&self.features, rng).with_depth(self.depth)
     }
 
-    #[allow(dead_code)]
-    pub fn mutate_random_weight(&mut self, rng: &mut SmallRng) {
-        if let Some(w) = self.weights.choose_mut(rng) {
-            *w = sample_std(rng);
-        }
+    fn parameters(&self) -> Vec<f64> {
+        self.weights.clone()
     }
 
-    pub fn nudge_random_weight(&mut self, rng: &mut SmallRng) {
-        if let Some(w) = self.weights.choose_mut(rng) {
-            *w += sample_std(rng) / STD_DEV;
+    fn with_parameters(&self, parameters: Vec<f64>) -> GeneticAgent {
+        assert_eq!(
+            self.weights.len(),
+            parameters.len(),
+            "Expected one weight per feature"
+        );
+
+        GeneticAgent {
+            weights: parameters,
+            ..self.clone()
         }
     }
 
-    fn loss_function(&self, board: &TetrisBoard) -> f64 {
-        self.weights
-            .iter()
-            .zip(&HEURISTICS)
-            .fold(0.0, |acc, (w, h)| acc + w * h(board))
+    fn step_size(&self) -> f64 {
+        self.step_size
     }
 
-    pub fn from_genetic(
-        ctx: &mut Context,
-        eval_iterations: usize,
-        num_generations: usize,
-        population_size: usize,
-        selection_size: usize,
-        mutation_probability: f64,
-    ) -> GameResult<(GeneticAgent, f64)> {
-        let mut population = (0..population_size)
-            .map(|_| GeneticAgent::new())
-            .collect::<Vec<GeneticAgent>>();
-
-        let mut rng = SmallRng::from_entropy();
-        let mut state = TetrisState::new(ctx); // Reuse to avoid allocations
-        let mut fitness_values = vec![0.0; population_size];
-        let mut selection = vec![population[0]; selection_size];
-
-        let mut best_score = 0.0;
-        let mut best_agent = population[0];
-
-        for generation in 0..num_generations {
-            // Calculate population's fitness values
-            for (agent, fitness) in population.iter_mut().zip(fitness_values.iter_mut()) {
-                *fitness = agent.evaluate(&mut state, ctx, eval_iterations)?;
-            }
-
-            // Keep track of fittest individual and score
-            let (i, &score) = fitness_values
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(&b).unwrap())
-                .unwrap();
-
-            best_score = score;
-            best_agent = population[i];
+    fn set_step_size(&mut self, step_size: f64) {
+        self.step_size = step_size;
+    }
+}
 
-            println!("Generation {} :: {}", generation+1, best_score);
+/// Genetic algorithm: parents are selected by fitness, bred with crossover
+/// and mutated, while the fittest few carry over unchanged.
+#[derive(Serialize, Deserialize)]
+pub struct GeneticAlgorithm<G> {
+    config: GeneticConfig,
+    population: Vec<G>,
+}
 
-            // Avoid unnecessary computation on the last iteration
-            if generation == num_generations - 1 {
-                break;
-            }
+impl<G: Genome> GeneticAlgorithm<G> {
+    /// Starts from a population of random individuals like `template`.
+    pub fn new(template: &G, config: GeneticConfig, rng: &mut SmallRng) -> GeneticAlgorithm<G> {
+        assert!(config.population_size > 0, "Population must not be empty");
+        assert!(config.selection_size > 0, "Selection must not be empty");
 
-            // Normalize fitness values
-            let fitness_sum = fitness_values.iter().sum::<f64>();
-            fitness_values.iter_mut().for_each(|x| *x /= fitness_sum);
-
-            // Select individuals weighted by fitness.
-            // Uses roulette wheel selection
-            // Source: https://en.wikipedia.org/wiki/Selection_(genetic_algorithm)
-
-            fn roulette_wheel<T: Copy>(a: &[T], ps: &[f64], r: f64) -> T {
-                let mut total = 0.0;
-                for (&x, p) in a.iter().zip(ps) {
-                    total += p;
-                    if total > r {
-                        return x;
-                    }
-                }
-                unreachable!("Roulette wheel called on unnormalized probabilities")
-            }
+        let population = (0..config.population_size)
+            .map(|_| template.random(rng))
+            .collect();
 
-            selection.iter_mut().for_each(|individual| {
-                let r = rng.gen();
-                *individual = roulette_wheel(&population, &fitness_values, r);
-            });
-
-            population.iter_mut().for_each(|individual| {
-                let parent_a = selection.choose(&mut rng).unwrap();
-                let parent_b = selection.choose(&mut rng).unwrap();
-                let mut child = parent_a.breed(parent_b, 0.5);
-                if rng.gen_bool(mutation_probability) {
-                    child.nudge_random_weight(&mut rng);
-                }
-                *individual = child;
-            });
-        }
+        GeneticAlgorithm { config, population }
+    }
+}
 
-        Ok((best_agent, best_score))
+impl<G: Genome> Optimizer<G> for GeneticAlgorithm<G> {
+    fn ask(&mut self, _rng: &mut SmallRng) -> Vec<G> {
+        std::mem::take(&mut self.population)
     }
 
-    #[allow(dead_code)]
-    pub fn train_mutation_only(
-        &mut self,
-        ctx: &mut Context,
-        n: usize,
-        k: usize,
-        mut best_score: f64,
-    ) -> GameResult<f64> {
-        let mut best_weights = self.weights;
-        let mut state = TetrisState::new(ctx);
-
-        let mut rng = SmallRng::from_entropy();
-
-        for _ in 0..k {
-            let average_score = self.evaluate(&mut state, ctx, n)?;
-
-            if average_score > best_score {
-                best_weights = self.weights;
-                best_score = average_score;
+    fn tell(&mut self, population: Vec<G>, fitness_values: &[f64], rng: &mut SmallRng) {
+        let config = &self.config;
+        let ranking = by_fitness(fitness_values);
+
+        let selection = config
+            .selection
+            .select(fitness_values, config.selection_size, rng)
+            .into_iter()
+            .map(|i| (&population[i], fitness_values[i]))
+            .collect::<Vec<(&G, f64)>>();
+
+        // Elites carry over unchanged, the rest of the generation is bred
+        // from the selection
+        let elites = config.elites.min(config.population_size);
+        let mut next = ranking[..elites]
+            .iter()
+            .map(|&i| population[i].clone())
+            .collect::<Vec<G>>();
+
+        while next.len() < config.population_size {
+            let (a, fitness_a) = selection.choose(rng).unwrap();
+            let (b, fitness_b) = selection.choose(rng).unwrap();
+            let mut child = a.crossover(*fitness_a, b, *fitness_b, config.crossover, rng);
+            if rng.gen_bool(config.mutation_probability) {
+                child.mutate(config.mutation, rng);
             }
-
-            self.mutate_random_weight(&mut rng);
+            next.push(child);
         }
-        self.weights = best_weights;
-        Ok(best_score)
-    }
-}
 
-impl Agent for GeneticAgent {
-    fn get_action(&mut self, state: &mut TetrisState) -> Option<KeyCode> {
-        if let Some(key) = state.pick_move_by_key(|board| self.loss_function(board)) {
-            Some(key)
-        } else {
-            None
-        }
+        self.population = next;
     }
 }
@@ -5,22 +5,14 @@ pub struct HumanAgent {
 }
 
 impl HumanAgent {
-    #[allow(dead_code)]
     pub fn new(events_loop: EventsLoop) -> HumanAgent {
         HumanAgent { events_loop }
     }
-}
 
-impl Agent for HumanAgent {
-    fn run(
-        &mut self,
-        _draw: DrawConfig,
-        ctx: &mut Context,
-        state: &mut TetrisState,
-    ) -> GameResult<()> {
-        // Simply hand off to the built-in event loop, as it will handle
-        // keyboard input for us. For the same reason we do not need to
-        // implement the get_action function.
-        event::run(ctx, &mut self.events_loop, state)
+    pub fn run(&mut self, ctx: &mut Context, state: &mut TetrisState) -> GameResult<()> {
+        // Simply hand off to the built-in event loop, as the front-end will
+        // handle keyboard input for us. Inputs arrive as events rather than
+        // being asked for, which is why this is not an `Agent`.
+        event::run(ctx, &mut self.events_loop, &mut Frontend::new(state))
     }
 }
@@ -1,82 +1,150 @@
 use crate::*;
 
-pub(crate) use ggez::{
-    event::{EventHandler, EventsLoop},
-    Context, GameResult,
-};
+use std::time::Instant;
+
+/// What an agent does on its turn: either a single input, or a complete
+/// placement that the engine applies in one step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Input(Input),
+    Place(Placement),
+}
 
-fn simulate_key_press(key: KeyCode, ctx: &mut Context, state: &mut TetrisState) -> GameResult<()> {
-    let press_event = gen_key_event(key, true);
-    ctx.process_event(&press_event);
+pub trait Agent {
+    /// Performs the agent's next action. Single inputs advance the simulation
+    /// by one tick, placements lock the current piece immediately.
+    fn step(&mut self, state: &mut TetrisState) {
+        match self.get_action(state) {
+            Some(Action::Place(placement)) => state.apply_placement(&placement),
+            Some(Action::Input(input)) => {
+                state.apply(input);
+                state.tick();
+            }
+            None => {
+                state.apply(Input::Drop);
+                state.tick();
+            }
+        }
+    }
 
-    state.update(ctx)?;
+    /// Plays until the game is over, without rendering anything, or until
+    /// the piece cap or time limit in `eval` is reached.
+    fn play_limited(&mut self, state: &mut TetrisState, eval: &EvalConfig) {
+        let start = Instant::now();
 
-    let release_event = gen_key_event(key, false);
-    ctx.process_event(&release_event);
+        while !state.is_over {
+            let out_of_pieces = eval.max_pieces.is_some_and(|n| state.pieces >= n);
+            let out_of_time = eval.time_limit.is_some_and(|t| start.elapsed() >= t);
+            if out_of_pieces || out_of_time {
+                break;
+            }
 
-    Ok(())
-}
+            self.step(state);
+        }
+    }
 
-pub trait Agent {
+    #[cfg(feature = "gui")]
     fn run(
         &mut self,
         draw: DrawConfig,
         ctx: &mut Context,
         state: &mut TetrisState,
     ) -> GameResult<()> {
-        while !state.is_over {
-            if let Some(key) = self.get_action(state) {
-                simulate_key_press(key, ctx, state)?;
+        let mut frame: u64 = 0;
+        let mut draw_frame = |ctx: &mut Context, state: &TetrisState| {
+            frame += 1;
+            if draw.draws(frame) {
+                state.draw(ctx)
             } else {
-                simulate_key_press(KeyCode::Space, ctx, state)?;
+                Ok(())
             }
+        };
 
-            state.update(ctx)?;
-
-            match draw {
-                DrawConfig::AllFrame => state.draw(ctx)?,
-                DrawConfig::NoFrames => (),
-                DrawConfig::EveryNFrames(n) => {
-                    if state.drop_count % n == 0 {
-                        state.draw(ctx)?
+        while !state.is_over {
+            match self.get_action(state) {
+                // Expand placements into their inputs so they can be watched
+                Some(Action::Place(placement)) => {
+                    for &input in &placement.path {
+                        state.apply(input);
+                        draw_frame(ctx, state)?;
                     }
+                    // The path ends in a hard drop, so the next tick locks
+                    // the piece
+                    state.tick();
+                }
+                Some(Action::Input(input)) => {
+                    state.apply(input);
+                    state.tick();
+                }
+                None => {
+                    state.apply(Input::Drop);
+                    state.tick();
                 }
             }
+
+            draw_frame(ctx, state)?;
         }
 
         Ok(())
     }
 
-    fn get_action(&mut self, _state: &mut TetrisState) -> Option<KeyCode> {
-        unimplemented!()
-    }
+    /// The agent's next action, or `None` to hard drop where the piece is.
+    fn get_action(&mut self, state: &TetrisState) -> Option<Action>;
 
-    fn evaluate(
-        &mut self,
-        state: &mut TetrisState,
-        ctx: &mut Context,
-        n: usize,
-    ) -> GameResult<f64> {
-        let mut scores = vec![0; n];
-
-        state.reset();
-        for i in 0..n {
-            self.run(DrawConfig::NoFrames, ctx, state)?;
-            scores[i] = state.score;
-            state.reset();
+    /// Fitness over one game per seed, combining the scores as set out in
+    /// `eval`.
+    fn evaluate_seeds(&mut self, state: &mut TetrisState, eval: &EvalConfig, seeds: &[u64]) -> f64 {
+        let mut scores = vec![0.0; seeds.len()];
+
+        for (score, &seed) in scores.iter_mut().zip(seeds) {
+            state.reset(seed);
+            self.play_limited(state, eval);
+            *score = state.score as f64;
         }
 
-        // Ok(scores[scores.len() / 2] as f64)           // median
-        // Ok(*scores.iter().min().unwrap() as f64)      // min
-        Ok(scores.iter().sum::<u32>() as f64 / n as f64) // mean
+        eval.aggregation.aggregate(&scores)
     }
 }
 
+#[cfg(feature = "gui")]
 mod human;
+#[cfg(feature = "gui")]
 pub use human::HumanAgent;
 
 mod random;
 pub use random::RandomAgent;
 
+mod evaluation;
+pub use evaluation::*;
+
+mod training;
+pub use training::*;
+
+mod selection;
+pub use selection::*;
+
+mod operators;
+pub use operators::*;
+
 mod genetic;
-pub use genetic::GeneticAgent;
+pub use genetic::{GeneticAgent, GeneticAlgorithm, GeneticConfig};
+
+mod reference;
+
+mod cmaes;
+pub use cmaes::{CmaEs, CmaesConfig};
+
+mod cem;
+pub use cem::{CemConfig, CrossEntropy};
+
+mod search;
+pub use search::{HillClimbing, RandomSearch, SearchConfig};
+
+mod saved;
+pub use saved::{SavedAgent, TrainingSummary};
+
+mod expectimax;
+pub use expectimax::ExpectimaxAgent;
+
+mod beam;
+pub use beam::BeamAgent;
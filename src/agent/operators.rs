@@ -0,0 +1,160 @@
+use crate::*;
+
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+// Spread of freshly drawn parameters. Somewhat arbitrarily chosen.
+const STD_DEV: f64 = 100.0;
+
+/// A fresh random parameter, normally distributed around zero.
+pub fn sample_std(rng: &mut SmallRng) -> f64 {
+    let r: f64 = rng.sample(StandardNormal);
+    r * STD_DEV
+}
+
+// Step sizes never shrink below this, so that mutation cannot switch itself off
+const MIN_STEP_SIZE: f64 = 1e-3;
+
+/// How two parents are combined into a child.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Crossover {
+    // Each weight taken from either parent with equal chance
+    Uniform,
+    // BLX-α: each weight drawn uniformly from the interval spanned by the
+    // parents, widened by α times its length on both sides
+    Blend(f64),
+    // Simulated binary crossover with distribution index η. Larger values
+    // keep children closer to their parents.
+    SimulatedBinary(f64),
+    // Average of the parents, weighted by their fitness
+    Arithmetic,
+}
+
+impl Crossover {
+    /// Parameters of a child bred from two parents, each given along with
+    /// its fitness.
+    pub fn cross(
+        &self,
+        a: &[f64],
+        fitness_a: f64,
+        b: &[f64],
+        fitness_b: f64,
+        rng: &mut SmallRng,
+    ) -> Vec<f64> {
+        assert_eq!(a.len(), b.len(), "Cannot breed genomes of different sizes");
+        let pairs = a.iter().zip(b);
+
+        match *self {
+            Crossover::Uniform => pairs
+                .map(|(&x, &y)| if rng.gen_bool(0.5) { x } else { y })
+                .collect(),
+            Crossover::Blend(alpha) => pairs
+                .map(|(&x, &y)| {
+                    let d = alpha * (x - y).abs();
+                    let (lo, hi) = (x.min(y) - d, x.max(y) + d);
+                    // Identical parents leave nothing to draw from
+                    if lo < hi {
+                        rng.gen_range(lo, hi)
+                    } else {
+                        x
+                    }
+                })
+                .collect(),
+            Crossover::SimulatedBinary(eta) => pairs
+                .map(|(&x, &y)| {
+                    let u: f64 = rng.gen();
+                    let beta = if u <= 0.5 {
+                        (2.0 * u).powf(1.0 / (eta + 1.0))
+                    } else {
+                        (1.0 / (2.0 * (1.0 - u))).powf(1.0 / (eta + 1.0))
+                    };
+                    // Either of the two children SBX produces
+                    let sign = if rng.gen() { 1.0 } else { -1.0 };
+                    0.5 * ((x + y) + sign * beta * (x - y))
+                })
+                .collect(),
+            Crossover::Arithmetic => {
+                // Negative fitness counts as none, and parents without any
+                // fitness are weighted equally
+                let (fa, fb) = (fitness_a.max(0.0), fitness_b.max(0.0));
+                let t = if fa + fb > 0.0 { fa / (fa + fb) } else { 0.5 };
+                pairs.map(|(&x, &y)| t * x + (1.0 - t) * y).collect()
+            }
+        }
+    }
+}
+
+/// Parses `uniform`, `blend:ALPHA`, `simulated-binary:ETA` or `arithmetic`.
+pub fn parse_crossover(spec: &str) -> Option<Crossover> {
+    let spec = spec.to_lowercase();
+    match spec.split_once(':') {
+        None => match spec.as_str() {
+            "uniform" => Some(Crossover::Uniform),
+            "arithmetic" => Some(Crossover::Arithmetic),
+            _ => None,
+        },
+        Some(("blend", alpha)) => alpha
+            .parse()
+            .ok()
+            .filter(|a| *a >= 0.0)
+            .map(Crossover::Blend),
+        Some(("simulated-binary", eta)) => eta
+            .parse()
+            .ok()
+            .filter(|e| *e >= 0.0)
+            .map(Crossover::SimulatedBinary),
+        _ => None,
+    }
+}
+
+/// How a child is changed after crossover.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Mutation {
+    // One weight shifted by a small random amount
+    NudgeOne,
+    // One weight replaced by a fresh random value
+    ResetOne,
+    // Every weight shifted by Gaussian noise with the agent's own step size,
+    // which is itself mutated first
+    SelfAdaptiveGaussian,
+}
+
+impl Mutation {
+    /// Mutates `parameters` in place. Only self-adaptive Gaussian mutation
+    /// reads or changes `step_size`.
+    pub fn mutate(&self, parameters: &mut [f64], step_size: &mut f64, rng: &mut SmallRng) {
+        match *self {
+            Mutation::NudgeOne => {
+                if let Some(w) = parameters.choose_mut(rng) {
+                    *w += sample_std(rng) / STD_DEV;
+                }
+            }
+            Mutation::ResetOne => {
+                if let Some(w) = parameters.choose_mut(rng) {
+                    *w = sample_std(rng);
+                }
+            }
+            Mutation::SelfAdaptiveGaussian => {
+                // Log-normal update with the usual learning rate of 1/sqrt(n)
+                let tau = 1.0 / (parameters.len() as f64).sqrt();
+                let r: f64 = rng.sample(StandardNormal);
+                *step_size = (*step_size * (tau * r).exp()).max(MIN_STEP_SIZE);
+
+                for w in parameters.iter_mut() {
+                    let r: f64 = rng.sample(StandardNormal);
+                    *w += *step_size * r;
+                }
+            }
+        }
+    }
+}
+
+/// Parses `nudge-one`, `reset-one` or `self-adaptive-gaussian`.
+pub fn parse_mutation(spec: &str) -> Option<Mutation> {
+    match spec.to_lowercase().as_str() {
+        "nudge-one" => Some(Mutation::NudgeOne),
+        "reset-one" => Some(Mutation::ResetOne),
+        "self-adaptive-gaussian" => Some(Mutation::SelfAdaptiveGaussian),
+        _ => None,
+    }
+}
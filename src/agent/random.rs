@@ -1,20 +1,27 @@
 use crate::*;
 
 pub struct RandomAgent {
-    rng: ThreadRng,
+    rng: SmallRng,
 }
 
 impl RandomAgent {
-    #[allow(dead_code)]
-    pub fn new() -> RandomAgent {
-        RandomAgent { rng: thread_rng() }
+    pub fn new(seed: u64) -> RandomAgent {
+        RandomAgent {
+            rng: SmallRng::seed_from_u64(seed),
+        }
     }
 }
 
 impl Agent for RandomAgent {
-    fn get_action(&mut self, _state: &mut TetrisState) -> Option<KeyCode> {
-        let possible_keys = &[KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down];
-        let key = *possible_keys.choose(&mut self.rng).unwrap();
-        Some(key)
+    fn get_action(&mut self, _state: &TetrisState) -> Option<Action> {
+        let possible_inputs = &[
+            Input::Left,
+            Input::Right,
+            Input::RotateCw,
+            Input::RotateCcw,
+            Input::Down,
+        ];
+        let input = *possible_inputs.choose(&mut self.rng).unwrap();
+        Some(Action::Input(input))
     }
 }
@@ -0,0 +1,57 @@
+use crate::*;
+
+// Published weightings, written as losses: each is the negation of the
+// original evaluation function, which is maximised.
+
+// Pierre Dellacherie's hand-tuned player, as described by Fahey (2003)
+const DELLACHERIE: [(&str, f64); 6] = [
+    ("landing-height", 1.0),
+    ("eroded-piece-cells", -1.0),
+    ("row-transitions", 1.0),
+    ("column-transitions", 1.0),
+    ("holes", 4.0),
+    ("cumulative-wells", 1.0),
+];
+
+// Weights found by Yiyuan Lee's El-Tetris with particle swarm optimisation.
+// Unlike Dellacherie, El-Tetris rewards the number of rows eliminated.
+const EL_TETRIS: [(&str, f64); 6] = [
+    ("landing-height", 4.500158825082766),
+    ("line-completion", -3.4181268101392694),
+    ("row-transitions", 3.2178882868487753),
+    ("column-transitions", 9.348695305445199),
+    ("holes", 7.899265427351652),
+    ("cumulative-wells", 3.3855972247263626),
+];
+
+fn from_table(table: &[(&str, f64)]) -> GeneticAgent {
+    let features = table
+        .iter()
+        .map(|&(name, _)| find_heuristic(name).expect("Unknown heuristic"))
+        .collect();
+    let weights = table.iter().map(|&(_, w)| w).collect();
+
+    GeneticAgent::with_weights(FeatureSet::new(features), weights)
+}
+
+/// Fixed, known-strong players to compare trained agents against. They use
+/// the same one-piece placement search as any `GeneticAgent`, so unlike the
+/// originals they do not break ties between equally scored placements.
+impl GeneticAgent {
+    pub fn dellacherie() -> GeneticAgent {
+        from_table(&DELLACHERIE)
+    }
+
+    pub fn el_tetris() -> GeneticAgent {
+        from_table(&EL_TETRIS)
+    }
+
+    /// Reference agent by name: `dellacherie` or `el-tetris`.
+    pub fn reference(name: &str) -> Option<GeneticAgent> {
+        match name.to_lowercase().as_str() {
+            "dellacherie" => Some(GeneticAgent::dellacherie()),
+            "el-tetris" => Some(GeneticAgent::el_tetris()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Version written into every agent file. Bumped whenever the format changes,
+/// since files of any other version are rejected on load.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// How a saved agent was trained.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingSummary {
+    pub trainer: String,
+    pub generations: usize,
+    // Fitness of the agent itself, as reported at the end of training
+    pub fitness: f64,
+    pub eval: EvalConfig,
+    // Fittest individual of every generation
+    pub best_per_generation: Vec<f64>,
+}
+
+/// A weighted agent along with everything needed to play it again: the
+/// names of its heuristics, the rules it was trained for and how it was
+/// trained.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedAgent {
+    pub version: u32,
+    pub features: FeatureSet,
+    pub weights: Vec<f64>,
+    pub depth: usize,
+    pub ruleset: Ruleset,
+    // Missing for agents that were never trained, such as reference agents
+    pub training: Option<TrainingSummary>,
+}
+
+impl SavedAgent {
+    pub fn new(
+        agent: &GeneticAgent,
+        ruleset: Ruleset,
+        training: Option<TrainingSummary>,
+    ) -> SavedAgent {
+        SavedAgent {
+            version: FORMAT_VERSION,
+            features: agent.features.clone(),
+            weights: agent.weights.clone(),
+            depth: agent.depth,
+            ruleset,
+            training,
+        }
+    }
+
+    pub fn agent(&self) -> GeneticAgent {
+        GeneticAgent::with_weights(self.features.clone(), self.weights.clone())
+            .with_depth(self.depth)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        // Pretty printed, since agent files are small and worth reading
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<SavedAgent> {
+        let saved: SavedAgent = load_versioned(path, "agent", FORMAT_VERSION)?;
+        if saved.weights.len() != saved.features.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Expected one weight per feature",
+            ));
+        }
+
+        Ok(saved)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Hyperparameters of `RandomSearch` and `HillClimbing`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SearchConfig {
+    pub num_generations: usize,
+    // Individuals evaluated each generation
+    pub population_size: usize,
+    // How hill climbing derives neighbours from the current individual
+    pub mutation: Mutation,
+}
+
+/// Pure random search, drawing a fresh random population every generation.
+#[derive(Serialize, Deserialize)]
+pub struct RandomSearch<G> {
+    config: SearchConfig,
+    template: G,
+}
+
+impl<G: Genome> RandomSearch<G> {
+    pub fn new(template: &G, config: SearchConfig) -> RandomSearch<G> {
+        RandomSearch {
+            config,
+            template: template.clone(),
+        }
+    }
+}
+
+impl<G: Genome> Optimizer<G> for RandomSearch<G> {
+    fn ask(&mut self, rng: &mut SmallRng) -> Vec<G> {
+        (0..self.config.population_size)
+            .map(|_| self.template.random(rng))
+            .collect()
+    }
+
+    fn tell(&mut self, _population: Vec<G>, _fitness_values: &[f64], _rng: &mut SmallRng) {}
+}
+
+/// Hill climbing: every generation evaluates mutated neighbours of the
+/// current individual, along with the individual itself, and moves to the
+/// fittest of them. Re-evaluating the current individual keeps one lucky
+/// evaluation from anchoring the search.
+#[derive(Serialize, Deserialize)]
+pub struct HillClimbing<G> {
+    config: SearchConfig,
+    current: G,
+}
+
+impl<G: Genome> HillClimbing<G> {
+    /// Starts from a random individual like `template`.
+    pub fn new(template: &G, config: SearchConfig, rng: &mut SmallRng) -> HillClimbing<G> {
+        HillClimbing {
+            config,
+            current: template.random(rng),
+        }
+    }
+}
+
+impl<G: Genome> Optimizer<G> for HillClimbing<G> {
+    fn ask(&mut self, rng: &mut SmallRng) -> Vec<G> {
+        let mut population = vec![self.current.clone()];
+        while population.len() < self.config.population_size.max(2) {
+            let mut neighbour = self.current.clone();
+            neighbour.mutate(self.config.mutation, rng);
+            population.push(neighbour);
+        }
+        population
+    }
+
+    fn tell(&mut self, mut population: Vec<G>, fitness_values: &[f64], _rng: &mut SmallRng) {
+        let fittest = by_fitness(fitness_values)[0];
+        self.current = population.swap_remove(fittest);
+    }
+}
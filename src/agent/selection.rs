@@ -0,0 +1,115 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+/// How parents are picked from a population, given everyone's fitness.
+/// Source: https://en.wikipedia.org/wiki/Selection_(genetic_algorithm)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Selection {
+    // Chance proportional to fitness
+    RouletteWheel,
+    // Fittest of this many individuals drawn at random
+    Tournament(usize),
+    // Chance proportional to rank, the least fit having rank one
+    Rank,
+    // The fittest individuals, each picked once
+    Truncation,
+    // Roulette wheel spun once with evenly spaced pointers
+    StochasticUniversalSampling,
+}
+
+impl Selection {
+    /// Indices of `n` individuals picked from a population with the given
+    /// fitness values. Any individual can be picked more than once, except
+    /// under truncation.
+    pub fn select(&self, fitness: &[f64], n: usize, rng: &mut SmallRng) -> Vec<usize> {
+        match *self {
+            Selection::RouletteWheel => {
+                let weights = shifted(fitness);
+                (0..n).map(|_| spin(&weights, rng.gen())).collect()
+            }
+            Selection::Tournament(size) => (0..n)
+                .map(|_| {
+                    (0..size.max(1))
+                        .map(|_| rng.gen_range(0, fitness.len()))
+                        .max_by(|&a, &b| fitness[a].partial_cmp(&fitness[b]).unwrap())
+                        .unwrap()
+                })
+                .collect(),
+            Selection::Rank => {
+                let mut weights = vec![0.0; fitness.len()];
+                for (rank, i) in by_fitness(fitness).into_iter().rev().enumerate() {
+                    weights[i] = (rank + 1) as f64;
+                }
+                (0..n).map(|_| spin(&weights, rng.gen())).collect()
+            }
+            Selection::Truncation => {
+                let mut order = by_fitness(fitness);
+                order.truncate(n);
+                order
+            }
+            Selection::StochasticUniversalSampling => {
+                let weights = shifted(fitness);
+                let offset: f64 = rng.gen();
+                (0..n)
+                    .map(|i| spin(&weights, (i as f64 + offset) / n as f64))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Parses `roulette-wheel`, `tournament:SIZE`, `rank`, `truncation` or
+/// `stochastic-universal-sampling`.
+pub fn parse_selection(spec: &str) -> Option<Selection> {
+    let spec = spec.to_lowercase();
+    match spec.split_once(':') {
+        None => match spec.as_str() {
+            "roulette-wheel" => Some(Selection::RouletteWheel),
+            "rank" => Some(Selection::Rank),
+            "truncation" => Some(Selection::Truncation),
+            "stochastic-universal-sampling" => Some(Selection::StochasticUniversalSampling),
+            _ => None,
+        },
+        Some(("tournament", size)) => size
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .map(Selection::Tournament),
+        _ => None,
+    }
+}
+
+/// Indices of the population from the fittest individual down.
+pub fn by_fitness(fitness: &[f64]) -> Vec<usize> {
+    let mut order = (0..fitness.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| fitness[b].partial_cmp(&fitness[a]).unwrap());
+    order
+}
+
+// Fitness shifted so that the least fit individual gets no share of the
+// wheel, which keeps zero and negative fitness values meaningful
+fn shifted(fitness: &[f64]) -> Vec<f64> {
+    let min = fitness.iter().cloned().fold(f64::INFINITY, f64::min);
+    fitness.iter().map(|x| x - min).collect()
+}
+
+// Individual under the pointer at `r`, in [0, 1), of a wheel where each slice
+// is proportional to its weight. Equal slices are used if every weight is zero.
+fn spin(weights: &[f64], r: f64) -> usize {
+    let sum = weights.iter().sum::<f64>();
+    if sum <= 0.0 {
+        return ((r * weights.len() as f64) as usize).min(weights.len() - 1);
+    }
+
+    let mut total = 0.0;
+    for (i, w) in weights.iter().enumerate() {
+        total += w / sum;
+        if total > r {
+            return i;
+        }
+    }
+
+    // Rounding can leave the total just short of one
+    weights.iter().rposition(|&w| w > 0.0).unwrap()
+}
@@ -0,0 +1,350 @@
+use crate::*;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// An agent whose behaviour is set by a vector of real parameters, which lets
+/// any `Optimizer` train it.
+pub trait Genome: Agent + Clone + Send {
+    /// Individual of the same kind as `self`, with random parameters.
+    fn random(&self, rng: &mut SmallRng) -> Self;
+
+    fn parameters(&self) -> Vec<f64>;
+
+    /// Copy of `self` with its parameters replaced.
+    fn with_parameters(&self, parameters: Vec<f64>) -> Self;
+
+    /// Mutation step size carried by the individual, for self-adaptive
+    /// mutation. Genomes without one mutate with a fixed step size of one.
+    fn step_size(&self) -> f64 {
+        1.0
+    }
+
+    fn set_step_size(&mut self, _step_size: f64) {}
+
+    /// Child of `self` and `other`, each given along with its fitness. The
+    /// child inherits the geometric mean of its parents' step sizes.
+    fn crossover(
+        &self,
+        fitness: f64,
+        other: &Self,
+        other_fitness: f64,
+        operator: Crossover,
+        rng: &mut SmallRng,
+    ) -> Self {
+        let parameters = operator.cross(
+            &self.parameters(),
+            fitness,
+            &other.parameters(),
+            other_fitness,
+            rng,
+        );
+
+        let mut child = self.with_parameters(parameters);
+        child.set_step_size((self.step_size() * other.step_size()).sqrt());
+        child
+    }
+
+    fn mutate(&mut self, operator: Mutation, rng: &mut SmallRng) {
+        let mut parameters = self.parameters();
+        let mut step_size = self.step_size();
+        operator.mutate(&mut parameters, &mut step_size, rng);
+
+        *self = self.with_parameters(parameters);
+        self.set_step_size(step_size);
+    }
+}
+
+/// A search over genomes, driven by `optimize` one generation at a time.
+pub trait Optimizer<G: Genome> {
+    /// Individuals to evaluate in the next generation.
+    fn ask(&mut self, rng: &mut SmallRng) -> Vec<G>;
+
+    /// Fitness of the individuals from the last `ask`, in the same order.
+    fn tell(&mut self, population: Vec<G>, fitness_values: &[f64], rng: &mut SmallRng);
+
+    /// The optimizer's own pick once training is over, if it would rather
+    /// not just return the fittest individual seen.
+    fn recommend(&self) -> Option<G> {
+        None
+    }
+}
+
+// Version written into every checkpoint
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// A training run, which can be saved after any generation and resumed later
+/// to continue exactly as if it had never stopped.
+#[derive(Serialize, Deserialize)]
+pub struct Training<G, O> {
+    pub version: u32,
+    pub trainer: String,
+    pub ruleset: Ruleset,
+    pub eval: EvalConfig,
+    pub num_generations: usize,
+    // Generations completed so far
+    pub generation: usize,
+    // Seed of the generator for the next generation. Generators cannot be
+    // saved, so a fresh one is seeded at the start of every generation.
+    seed: u64,
+    // Fitness of every individual, generation by generation
+    pub history: Vec<Vec<f64>>,
+    best: Option<(G, f64)>,
+    optimizer: O,
+}
+
+impl<G: Genome + Serialize, O: Optimizer<G> + Serialize> Training<G, O> {
+    pub fn new(
+        trainer: &str,
+        ruleset: Ruleset,
+        optimizer: O,
+        eval: EvalConfig,
+        num_generations: usize,
+        rng: &mut SmallRng,
+    ) -> Training<G, O> {
+        Training {
+            version: CHECKPOINT_VERSION,
+            trainer: trainer.to_string(),
+            ruleset,
+            eval,
+            num_generations,
+            generation: 0,
+            seed: rng.gen(),
+            history: Vec::new(),
+            best: None,
+            optimizer,
+        }
+    }
+
+    /// Runs the remaining generations, saving the run to `checkpoint` after
+    /// each one. Returns the fittest individual seen, or the optimizer's
+    /// recommendation scored on fresh games, with its fitness.
+    pub fn run(
+        &mut self,
+        state: &TetrisState,
+        checkpoint: Option<&Path>,
+    ) -> io::Result<Option<(G, f64)>> {
+        let mut rng = SmallRng::seed_from_u64(self.seed);
+        let rng = &mut rng;
+
+        while self.generation < self.num_generations {
+            let mut population = self.optimizer.ask(rng);
+            let fitness_values = evaluate_population(&mut population, state, &self.eval, rng);
+            log_generation(self.generation, &fitness_values);
+
+            let ranking = by_fitness(&fitness_values);
+            if let Some(&i) = ranking.first() {
+                if self
+                    .best
+                    .as_ref()
+                    .is_none_or(|(_, score)| fitness_values[i] > *score)
+                {
+                    self.best = Some((population[i].clone(), fitness_values[i]));
+                }
+            }
+
+            self.optimizer.tell(population, &fitness_values, rng);
+            self.history.push(fitness_values);
+            self.generation += 1;
+
+            self.seed = rng.gen();
+            *rng = SmallRng::seed_from_u64(self.seed);
+            if let Some(path) = checkpoint {
+                self.save(path)?;
+            }
+        }
+
+        if let Some(agent) = self.optimizer.recommend() {
+            let mut population = vec![agent];
+            let score = evaluate_population(&mut population, state, &self.eval, rng)[0];
+            return Ok(population.pop().map(|agent| (agent, score)));
+        }
+
+        Ok(self.best.clone())
+    }
+
+    /// Summary of the run so far, for an agent with the given fitness.
+    pub fn summary(&self, fitness: f64) -> TrainingSummary {
+        TrainingSummary {
+            trainer: self.trainer.clone(),
+            generations: self.generation,
+            fitness,
+            eval: self.eval,
+            best_per_generation: self
+                .history
+                .iter()
+                .map(|fitness_values| {
+                    fitness_values
+                        .iter()
+                        .cloned()
+                        .fold(f64::NEG_INFINITY, f64::max)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        // Written in full before replacing the previous checkpoint, so that
+        // an interrupted save leaves the previous one intact
+        let partial = path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(partial, path)
+    }
+}
+
+impl<G: DeserializeOwned, O: DeserializeOwned> Training<G, O> {
+    pub fn load(path: &Path) -> io::Result<Training<G, O>> {
+        load_versioned(path, "checkpoint", CHECKPOINT_VERSION)
+    }
+}
+
+/// Name of the trainer that saved the checkpoint at `path`, which decides
+/// the type it is loaded as.
+pub fn checkpoint_trainer(path: &Path) -> io::Result<String> {
+    #[derive(Deserialize)]
+    struct Header {
+        trainer: String,
+    }
+
+    let header: Header = load_versioned(path, "checkpoint", CHECKPOINT_VERSION)?;
+    Ok(header.trainer)
+}
+
+/// Progress report printed by every trainer after evaluating a generation.
+pub fn log_generation(generation: usize, fitness_values: &[f64]) {
+    let best = fitness_values
+        .iter()
+        .cloned()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let mean = fitness_values.iter().sum::<f64>() / fitness_values.len() as f64;
+
+    println!(
+        "Generation {} :: {} (mean {:.1})",
+        generation + 1,
+        best,
+        mean
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENERATIONS: usize = 3;
+    const POPULATION: usize = 6;
+
+    fn eval() -> EvalConfig {
+        EvalConfig {
+            iterations: 2,
+            common_seeds: true,
+            aggregation: Aggregation::Mean,
+            max_pieces: Some(30),
+            time_limit: None,
+        }
+    }
+
+    // Trained agent, its fitness and the fitness of every generation
+    type Trained = (GeneticAgent, f64, Vec<Vec<f64>>);
+
+    // Trains with the optimizer built by `optimizer` for `generations`, then
+    // resumes from the checkpoint for the rest, if there is any
+    fn train<O, F>(name: &str, optimizer: F, generations: usize) -> Trained
+    where
+        O: Optimizer<GeneticAgent> + Serialize + DeserializeOwned,
+        F: Fn(&GeneticAgent, &mut SmallRng) -> O,
+    {
+        let ruleset = Ruleset::new("7-bag", 1);
+        let state = ruleset.new_state(0).unwrap();
+        let features = parse_features("basic").unwrap();
+        let template = GeneticAgent::with_weights(features.clone(), vec![0.0; features.len()]);
+        let path = std::env::temp_dir().join(format!(
+            "genetic-tetris-{}-{}-{}.json",
+            name,
+            generations,
+            std::process::id()
+        ));
+
+        let mut rng = SmallRng::seed_from_u64(1);
+        let optimizer = optimizer(&template, &mut rng);
+        let mut training = Training::new(name, ruleset, optimizer, eval(), generations, &mut rng);
+        let mut trained = training.run(&state, Some(&path)).unwrap();
+
+        if generations < GENERATIONS {
+            training = Training::load(&path).unwrap();
+            training.num_generations = GENERATIONS;
+            trained = training.run(&state, Some(&path)).unwrap();
+        }
+
+        fs::remove_file(&path).unwrap();
+        let (agent, fitness) = trained.unwrap();
+        (agent, fitness, training.history)
+    }
+
+    fn assert_resumes_exactly<O, F>(name: &str, optimizer: F)
+    where
+        O: Optimizer<GeneticAgent> + Serialize + DeserializeOwned,
+        F: Fn(&GeneticAgent, &mut SmallRng) -> O,
+    {
+        let (agent, fitness, history) = train(name, &optimizer, GENERATIONS);
+        let (resumed, resumed_fitness, resumed_history) = train(name, &optimizer, 1);
+        assert_eq!(agent.weights, resumed.weights, "{}", name);
+        assert_eq!(fitness, resumed_fitness, "{}", name);
+        assert_eq!(history, resumed_history, "{}", name);
+    }
+
+    #[test]
+    fn resumed_training_matches_uninterrupted() {
+        let genetic = GeneticConfig {
+            num_generations: GENERATIONS,
+            population_size: POPULATION,
+            selection_size: 2,
+            selection: Selection::Tournament(2),
+            elites: 1,
+            crossover: Crossover::Blend(0.5),
+            mutation: Mutation::SelfAdaptiveGaussian,
+            mutation_probability: 0.5,
+        };
+        assert_resumes_exactly("genetic", |template, rng| {
+            GeneticAlgorithm::new(template, genetic, rng)
+        });
+
+        let cmaes = CmaesConfig {
+            num_generations: GENERATIONS,
+            population_size: POPULATION,
+            step_size: 50.0,
+            max_restarts: 1,
+            stagnation: 1,
+        };
+        assert_resumes_exactly("cma-es", |template, rng| CmaEs::new(template, cmaes, rng));
+
+        let cem = CemConfig {
+            num_generations: GENERATIONS,
+            population_size: POPULATION,
+            elite_fraction: 0.5,
+            step_size: 10.0,
+            noise: 4.0,
+            noise_decay: 0.1,
+        };
+        assert_resumes_exactly("cross-entropy", |template, _| {
+            CrossEntropy::new(template, cem)
+        });
+
+        let search = SearchConfig {
+            num_generations: GENERATIONS,
+            population_size: POPULATION,
+            mutation: Mutation::ResetOne,
+        };
+        assert_resumes_exactly("random-search", |template, _| {
+            RandomSearch::new(template, search)
+        });
+        assert_resumes_exactly("hill-climbing", |template, rng| {
+            HillClimbing::new(template, search, rng)
+        });
+    }
+}
@@ -0,0 +1,440 @@
+use crate::*;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Trains, plays and benchmarks Tetris agents")]
+// Only ever parsed once, at startup
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    /// Trains an agent and saves it to a file
+    Train(TrainArgs),
+    /// Plays a game with the keyboard
+    #[cfg(feature = "gui")]
+    Play(PlayArgs),
+    /// Renders a saved agent playing a game
+    #[cfg(feature = "gui")]
+    Watch(WatchArgs),
+    /// Plays a saved agent without rendering, reporting statistics over many
+    /// games
+    Bench(BenchArgs),
+    /// Plays back a recorded game
+    Replay(ReplayArgs),
+}
+
+/// Rules a new game is played under.
+#[derive(Debug, StructOpt)]
+pub struct RulesArgs {
+    /// One of "uniform", "7-bag", "14-bag", "tgm", "history-N" or
+    /// "fixed:IJLOSTZ"
+    #[structopt(long, default_value = "uniform")]
+    pub randomizer: String,
+    /// Number of upcoming pieces shown to players and agents
+    #[structopt(long, default_value = "5")]
+    pub preview_len: usize,
+}
+
+impl RulesArgs {
+    pub fn ruleset(&self) -> Ruleset {
+        Ruleset::new(&self.randomizer, self.preview_len)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TrainArgs {
+    /// Master seed that every other generator is derived from, drawn from
+    /// entropy if not given
+    #[structopt(long)]
+    pub seed: Option<u64>,
+    /// One of "genetic", "cma-es", "cross-entropy", "random-search" or
+    /// "hill-climbing"
+    #[structopt(long, default_value = "genetic")]
+    pub trainer: String,
+    /// "all", a preset ("basic", "dellacherie", "bcts") or a comma-separated
+    /// list of heuristic names, such as "holes,height,row-transitions"
+    #[structopt(long, default_value = "all")]
+    pub features: String,
+    #[structopt(flatten)]
+    pub rules: RulesArgs,
+    /// Threads used to evaluate the population, zero meaning one per core.
+    /// The outcome of training is the same whatever the number of threads.
+    #[structopt(long, default_value = "0")]
+    pub threads: usize,
+    /// Pieces the trained agent plans ahead for when playing, including the
+    /// current one. Training itself only searches a single ply.
+    #[structopt(long, default_value = "1")]
+    pub search_depth: usize,
+    /// Where training is saved after every generation
+    #[structopt(long, default_value = "checkpoint.json")]
+    pub checkpoint: PathBuf,
+    /// Does not save checkpoints
+    #[structopt(long)]
+    pub no_checkpoint: bool,
+    /// Continues the training saved in this checkpoint, ignoring every
+    /// hyperparameter given
+    #[structopt(long)]
+    pub resume: Option<PathBuf>,
+    /// Where the trained agent is saved
+    #[structopt(short, long, default_value = "agent.json")]
+    pub output: PathBuf,
+
+    /// Games played by each individual
+    #[structopt(long, default_value = "5", parse(try_from_str = positive))]
+    pub eval_iterations: usize,
+    /// Lets every individual play different piece sequences
+    #[structopt(long)]
+    pub no_common_seeds: bool,
+    /// One of "mean", "median", "min", "trimmed-mean:FRACTION",
+    /// "lower-confidence-bound:Z" or "cvar:ALPHA"
+    #[structopt(long, default_value = "mean", parse(try_from_str = aggregation))]
+    pub aggregation: Aggregation,
+    /// Games are cut short after this many pieces, zero meaning never
+    #[structopt(long, default_value = "2000")]
+    pub max_pieces: usize,
+    /// Games are also cut short after this many seconds. Unlike the piece
+    /// cap, this makes fitness depend on the speed of the machine.
+    #[structopt(long, parse(try_from_str = non_negative))]
+    pub time_limit: Option<f64>,
+
+    /// Generations to train for. Defaults to 3 for "genetic", 30 for
+    /// "cross-entropy" and 50 otherwise.
+    #[structopt(long)]
+    pub generations: Option<usize>,
+    /// Individuals evaluated each generation. Defaults to 500 for "genetic",
+    /// 4 + 3 ln(n) for "cma-es", 100 for "cross-entropy" and 20 otherwise.
+    #[structopt(long, parse(try_from_str = positive))]
+    pub population_size: Option<usize>,
+    /// Initial standard deviation of the search. Defaults to 50 for "cma-es"
+    /// and 10 for "cross-entropy".
+    #[structopt(long)]
+    pub step_size: Option<f64>,
+
+    /// Parents picked each generation, a tenth of the population by default
+    #[structopt(long, parse(try_from_str = positive))]
+    pub selection_size: Option<usize>,
+    /// One of "roulette-wheel", "tournament:SIZE", "rank", "truncation" or
+    /// "stochastic-universal-sampling"
+    #[structopt(long, default_value = "tournament:3", parse(try_from_str = selection))]
+    pub selection: Selection,
+    /// Fittest individuals copied unchanged into the next generation, a
+    /// hundredth of the population by default
+    #[structopt(long)]
+    pub elites: Option<usize>,
+    /// One of "uniform", "blend:ALPHA", "simulated-binary:ETA" or
+    /// "arithmetic"
+    #[structopt(long, default_value = "blend:0.5", parse(try_from_str = crossover))]
+    pub crossover: Crossover,
+    /// One of "nudge-one", "reset-one" or "self-adaptive-gaussian". Defaults
+    /// to "self-adaptive-gaussian" for "genetic" and "reset-one" for
+    /// "hill-climbing".
+    #[structopt(long, parse(try_from_str = mutation))]
+    pub mutation: Option<Mutation>,
+    /// Chance of a child being mutated at all
+    #[structopt(long, default_value = "0.15", parse(try_from_str = probability))]
+    pub mutation_probability: f64,
+
+    /// Restarts of "cma-es" with a doubled population once the search stalls
+    #[structopt(long, default_value = "3")]
+    pub max_restarts: usize,
+    /// Generations without a new best individual before "cma-es" restarts
+    #[structopt(long, default_value = "15", parse(try_from_str = positive))]
+    pub stagnation: usize,
+
+    /// Fraction of each generation that "cross-entropy" refits to
+    #[structopt(long, default_value = "0.1", parse(try_from_str = probability))]
+    pub elite_fraction: f64,
+    /// Variance that "cross-entropy" adds to every weight after refitting
+    #[structopt(long, default_value = "4", parse(try_from_str = non_negative))]
+    pub noise: f64,
+    /// Reduction of the added variance every generation
+    #[structopt(long, default_value = "0.1", parse(try_from_str = non_negative))]
+    pub noise_decay: f64,
+}
+
+impl TrainArgs {
+    pub fn eval_config(&self) -> EvalConfig {
+        EvalConfig {
+            iterations: self.eval_iterations,
+            common_seeds: !self.no_common_seeds,
+            aggregation: self.aggregation,
+            max_pieces: Some(self.max_pieces).filter(|&n| n > 0),
+            time_limit: self.time_limit.map(Duration::from_secs_f64),
+        }
+    }
+
+    pub fn genetic_config(&self) -> GeneticConfig {
+        let population_size = self.population_size.unwrap_or(500);
+        GeneticConfig {
+            num_generations: self.generations.unwrap_or(3),
+            population_size,
+            // Small populations still need a parent to breed from
+            selection_size: self.selection_size.unwrap_or((population_size / 10).max(1)),
+            selection: self.selection,
+            elites: self.elites.unwrap_or(population_size / 100),
+            crossover: self.crossover,
+            mutation: self.mutation.unwrap_or(Mutation::SelfAdaptiveGaussian),
+            mutation_probability: self.mutation_probability,
+        }
+    }
+
+    pub fn cmaes_config(&self) -> CmaesConfig {
+        CmaesConfig {
+            num_generations: self.generations.unwrap_or(50),
+            population_size: self.population_size.unwrap_or(0),
+            step_size: self.step_size.unwrap_or(50.0),
+            max_restarts: self.max_restarts,
+            stagnation: self.stagnation,
+        }
+    }
+
+    pub fn cem_config(&self) -> CemConfig {
+        CemConfig {
+            num_generations: self.generations.unwrap_or(30),
+            population_size: self.population_size.unwrap_or(100),
+            elite_fraction: self.elite_fraction,
+            step_size: self.step_size.unwrap_or(10.0),
+            noise: self.noise,
+            noise_decay: self.noise_decay,
+        }
+    }
+
+    pub fn search_config(&self) -> SearchConfig {
+        SearchConfig {
+            num_generations: self.generations.unwrap_or(50),
+            population_size: self.population_size.unwrap_or(20),
+            mutation: self.mutation.unwrap_or(Mutation::ResetOne),
+        }
+    }
+
+    pub fn checkpoint(&self) -> Option<&Path> {
+        Some(self.checkpoint.as_path()).filter(|_| !self.no_checkpoint)
+    }
+}
+
+/// Agent to play, either saved to a file or built in.
+#[derive(Debug, StructOpt)]
+pub struct PlayerArgs {
+    /// Agent file to load
+    #[structopt(default_value = "agent.json")]
+    pub agent: PathBuf,
+    /// Plays a built-in reference player instead of a saved agent,
+    /// "dellacherie", "el-tetris" or "random", the last one pressing keys at
+    /// random. Reference players play under the rules given by --randomizer
+    /// and --preview-len, saved agents under their own.
+    #[structopt(long)]
+    pub reference: Option<String>,
+    #[structopt(flatten)]
+    pub rules: RulesArgs,
+    #[structopt(flatten)]
+    pub search: SearchArgs,
+}
+
+/// How a loaded agent searches for its placements.
+#[derive(Debug, StructOpt)]
+pub struct SearchArgs {
+    /// One of "lookahead", the agent's own search through the preview,
+    /// "expectimax", which also averages over the pieces past the preview, or
+    /// "beam", which only follows the most promising boards
+    #[structopt(long, default_value = "lookahead", parse(try_from_str = search))]
+    pub search: Search,
+    /// Pieces to plan for, including the current one. Defaults to the
+    /// agent's own depth for "lookahead", 2 for "expectimax" and 3 for
+    /// "beam".
+    #[structopt(long)]
+    pub depth: Option<usize>,
+    /// Placements "expectimax" expands at each piece, keeping those with the
+    /// lowest immediate loss, or boards "beam" keeps after each piece.
+    /// Defaults to 0 for "expectimax", meaning every placement, and 8 for
+    /// "beam".
+    #[structopt(long)]
+    pub width: Option<usize>,
+    /// Unseen pieces less likely than this are left out of the average of
+    /// "expectimax"
+    #[structopt(long, default_value = "0")]
+    pub min_probability: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Search {
+    Lookahead,
+    Expectimax,
+    Beam,
+}
+
+impl SearchArgs {
+    pub fn depth(&self, evaluator: &GeneticAgent) -> usize {
+        match self.search {
+            Search::Lookahead => self.depth.unwrap_or(evaluator.depth),
+            Search::Expectimax => self.depth.unwrap_or(2),
+            Search::Beam => self.depth.unwrap_or(3),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        match self.search {
+            Search::Lookahead | Search::Expectimax => self.width.unwrap_or(0),
+            Search::Beam => self.width.unwrap_or(8),
+        }
+    }
+
+    /// Agent searching as set out here, scoring boards with the weights of
+    /// `evaluator`.
+    pub fn agent(&self, evaluator: &GeneticAgent) -> Box<dyn Agent> {
+        let depth = self.depth(evaluator);
+        match self.search {
+            Search::Lookahead => Box::new(evaluator.clone().with_depth(depth)),
+            Search::Expectimax => Box::new(ExpectimaxAgent::new(
+                evaluator.clone(),
+                depth,
+                self.width(),
+                self.min_probability,
+            )),
+            Search::Beam => Box::new(BeamAgent::new(evaluator.clone(), self.width(), depth)),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, StructOpt)]
+pub struct PlayArgs {
+    /// Seed of the piece sequence, drawn from entropy if not given
+    #[structopt(long)]
+    pub seed: Option<u64>,
+    #[structopt(flatten)]
+    pub rules: RulesArgs,
+    /// Saves a replay of the game to this file
+    #[structopt(long)]
+    pub record: Option<PathBuf>,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Debug, StructOpt)]
+pub struct WatchArgs {
+    #[structopt(flatten)]
+    pub player: PlayerArgs,
+    /// Seed of the piece sequence, drawn from entropy if not given
+    #[structopt(long)]
+    pub seed: Option<u64>,
+    /// Frames to render: "all", "none" or "every:N"
+    #[structopt(long, default_value = "all", parse(try_from_str = draw))]
+    pub draw: DrawConfig,
+    /// Saves a replay of the game to this file
+    #[structopt(long)]
+    pub record: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct BenchArgs {
+    #[structopt(flatten)]
+    pub player: PlayerArgs,
+    /// Seed that the seed of every game is drawn from, drawn from entropy if
+    /// not given
+    #[structopt(long)]
+    pub seed: Option<u64>,
+    /// Games to play
+    #[structopt(long, default_value = "100")]
+    pub games: usize,
+    /// Games are cut short after this many pieces, zero meaning never
+    #[structopt(long, default_value = "0")]
+    pub max_pieces: usize,
+    /// Threads to play on, zero meaning one per core
+    #[structopt(long, default_value = "0")]
+    pub threads: usize,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ReplayArgs {
+    /// Replay file to play back
+    pub replay: PathBuf,
+    /// Frames to render: "all", "none" or "every:N"
+    #[cfg(feature = "gui")]
+    #[structopt(long, default_value = "all", parse(try_from_str = draw))]
+    pub draw: DrawConfig,
+}
+
+/// Which frames to render when watching agents and replays.
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Copy)]
+pub enum DrawConfig {
+    AllFrame,
+    NoFrames,
+    EveryNFrames(u64),
+}
+
+#[cfg(feature = "gui")]
+impl DrawConfig {
+    /// Whether to draw the given frame, counting from one.
+    pub fn draws(&self, frame: u64) -> bool {
+        match *self {
+            DrawConfig::AllFrame => true,
+            DrawConfig::NoFrames => false,
+            DrawConfig::EveryNFrames(n) => frame.is_multiple_of(n),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn draw(spec: &str) -> Result<DrawConfig, String> {
+    match spec.split_once(':') {
+        None if spec == "all" => Ok(DrawConfig::AllFrame),
+        None if spec == "none" => Ok(DrawConfig::NoFrames),
+        Some(("every", n)) => n
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .map(DrawConfig::EveryNFrames)
+            .ok_or_else(|| format!("Invalid frame interval: {}", n)),
+        _ => Err(format!("Unknown draw setting: {}", spec)),
+    }
+}
+
+fn positive(spec: &str) -> Result<usize, String> {
+    match spec.parse() {
+        Ok(0) => Err("Must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn probability(spec: &str) -> Result<f64, String> {
+    match spec.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        Ok(_) => Err("Must be between 0 and 1".to_string()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn non_negative(spec: &str) -> Result<f64, String> {
+    match spec.parse::<f64>() {
+        Ok(x) if x.is_finite() && x >= 0.0 => Ok(x),
+        Ok(_) => Err("Must be a finite number of at least 0".to_string()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn search(spec: &str) -> Result<Search, String> {
+    match spec {
+        "lookahead" => Ok(Search::Lookahead),
+        "expectimax" => Ok(Search::Expectimax),
+        "beam" => Ok(Search::Beam),
+        _ => Err(format!("Unknown search: {}", spec)),
+    }
+}
+
+fn aggregation(spec: &str) -> Result<Aggregation, String> {
+    parse_aggregation(spec).ok_or_else(|| format!("Unknown aggregation: {}", spec))
+}
+
+fn selection(spec: &str) -> Result<Selection, String> {
+    parse_selection(spec).ok_or_else(|| format!("Unknown selection: {}", spec))
+}
+
+fn crossover(spec: &str) -> Result<Crossover, String> {
+    parse_crossover(spec).ok_or_else(|| format!("Unknown crossover: {}", spec))
+}
+
+fn mutation(spec: &str) -> Result<Mutation, String> {
+    parse_mutation(spec).ok_or_else(|| format!("Unknown mutation: {}", spec))
+}
@@ -0,0 +1,309 @@
+use crate::*;
+
+pub use ggez::event::{self, EventHandler, EventsLoop};
+pub use ggez::{
+    conf,
+    graphics::{self, Color},
+    input::keyboard::{is_key_pressed, KeyCode},
+    Context, ContextBuilder, GameResult,
+};
+
+pub use std::collections::HashMap;
+
+pub const PIECE_SIZE: f32 = 30.0;
+pub const UNIT: f32 = PIECE_SIZE;
+
+// Width of the side panel to the right of the board, in board cells
+pub const PANEL_WIDTH: usize = 6;
+// First row of the preview, below the hold slot
+const PREVIEW_TOP: i32 = 5;
+// Vertical space given to each piece in the preview, in board cells
+const PREVIEW_SPACING: i32 = 3;
+
+/// Rows the window needs to fit both the board and a preview of
+/// `preview_len` pieces, which can run past the bottom of the board.
+pub fn window_rows(preview_len: usize) -> usize {
+    let preview_bottom = PREVIEW_TOP as usize + PREVIEW_SPACING as usize * preview_len;
+    BOARD_HEIGHT.max(preview_bottom)
+}
+
+// Number of ticks before a held key repeats
+pub const REPEAT_TICKS: u64 = 24;
+
+pub const KEY_BINDINGS: [(KeyCode, Input); 11] = [
+    (KeyCode::Up, Input::RotateCw),
+    (KeyCode::X, Input::RotateCw),
+    (KeyCode::Z, Input::RotateCcw),
+    (KeyCode::A, Input::Rotate180),
+    (KeyCode::Left, Input::Left),
+    (KeyCode::Right, Input::Right),
+    (KeyCode::Down, Input::Down),
+    (KeyCode::Space, Input::Drop),
+    (KeyCode::C, Input::Hold),
+    (KeyCode::LShift, Input::Hold),
+    (KeyCode::RShift, Input::Hold),
+];
+
+/// Keyboard-driven front-end over a `TetrisState`, used for human play.
+pub struct Frontend<'a> {
+    pub state: &'a mut TetrisState,
+    pub pressed_map: HashMap<KeyCode, bool>,
+    pub sub_count: u64,
+}
+
+impl<'a> Frontend<'a> {
+    pub fn new(state: &'a mut TetrisState) -> Frontend<'a> {
+        let mut pressed_map = HashMap::new();
+        for &(key, _) in &KEY_BINDINGS {
+            pressed_map.insert(key, false);
+        }
+
+        Frontend {
+            state,
+            pressed_map,
+            sub_count: 0,
+        }
+    }
+
+    pub fn key_handler(&mut self, ctx: &mut Context, key: KeyCode, input: Input) {
+        let is_pressed = self.pressed_map.entry(key).or_default();
+
+        if is_key_pressed(ctx, key) {
+            if !*is_pressed {
+                *is_pressed = true;
+                self.state.apply(input);
+                self.sub_count = 0;
+            }
+        } else {
+            *is_pressed = false;
+        }
+    }
+
+    pub fn input_handler(&mut self, ctx: &mut Context) {
+        self.sub_count += 1;
+
+        // Release movement keys periodically so that holding them repeats
+        if self.sub_count.is_multiple_of(REPEAT_TICKS) {
+            self.pressed_map.insert(KeyCode::Left, false);
+            self.pressed_map.insert(KeyCode::Right, false);
+            if !self.state.drop_count.is_multiple_of(TICKS_PER_DROP) {
+                self.pressed_map.insert(KeyCode::Down, false);
+            }
+        }
+
+        for &(key, input) in &KEY_BINDINGS {
+            self.key_handler(ctx, key, input);
+        }
+    }
+}
+
+impl TetrisState {
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::clear(ctx, graphics::WHITE);
+
+        let rect = graphics::Rect::new(
+            0.0,
+            UNIT * PIECE_SPAWN_OFFSET as f32,
+            UNIT * BOARD_WIDTH as f32,
+            1.0,
+        );
+
+        let mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            rect,
+            Color::new(0.6, 0.0, 0.0, 1.0),
+        )
+        .unwrap();
+
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
+        self.current_piece.draw(ctx, self.pos, 1.0)?;
+        self.current_piece.draw(ctx, self.ghost_pos, 0.25)?;
+        self.draw_map(ctx)?;
+        self.draw_panel(ctx)?;
+
+        let score_text = graphics::Text::new(format!("Score: {}", self.score));
+        graphics::draw(
+            ctx,
+            &score_text,
+            graphics::DrawParam::new().color(Color::from_rgb(0, 0, 0)),
+        )?;
+
+        if self.is_over {
+            self.fill_screen(ctx, Color::new(0.0, 0.0, 0.0, 0.9))?;
+            self.draw_centered(ctx, "Game Over!", Color::new(1.0, 0.3, 0.3, 1.0))?;
+        }
+
+        graphics::present(ctx)
+    }
+
+    pub fn draw_map(&self, ctx: &mut Context) -> GameResult<()> {
+        let mut mesh_builder = graphics::MeshBuilder::new();
+
+        let mut n = 0;
+
+        for (y, line) in self.board.iter().enumerate() {
+            for (x, &piece_color) in line.iter().enumerate() {
+                if piece_color == PieceColor::Empty {
+                    continue;
+                }
+                let color = piece_color.to_color();
+
+                let bounds =
+                    graphics::Rect::new(UNIT * x as f32, UNIT * y as f32, UNIT - 1.0, UNIT - 1.0);
+                mesh_builder.rectangle(graphics::DrawMode::fill(), bounds, color);
+                n += 1;
+            }
+        }
+
+        if n < 1 {
+            Ok(())
+        } else {
+            let mesh = mesh_builder.build(ctx).unwrap();
+            graphics::draw(ctx, &mesh, graphics::DrawParam::new())
+        }
+    }
+
+    pub fn draw_panel(&self, ctx: &mut Context) -> GameResult<()> {
+        let left = UNIT * BOARD_WIDTH as f32;
+
+        let rect = graphics::Rect::new(
+            left,
+            0.0,
+            UNIT * PANEL_WIDTH as f32,
+            UNIT * window_rows(self.preview_len) as f32,
+        );
+        let mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            rect,
+            Color::new(0.9, 0.9, 0.9, 1.0),
+        )
+        .unwrap();
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
+
+        let label = |ctx: &mut Context, text: &str, row: i32| {
+            let text = graphics::Text::new(text);
+            graphics::draw(
+                ctx,
+                &text,
+                graphics::DrawParam::new()
+                    .dest([left + UNIT, UNIT * row as f32 + UNIT / 2.0])
+                    .color(Color::from_rgb(0, 0, 0)),
+            )
+        };
+
+        label(ctx, "Hold", 0)?;
+        if let Some(piece) = self.hold {
+            // Dim the held piece while it cannot be swapped back
+            let alpha = if self.can_hold { 1.0 } else { 0.4 };
+            piece.draw(ctx, [BOARD_WIDTH as i32 + 1, 1], alpha)?;
+        }
+
+        label(ctx, "Next", PREVIEW_TOP - 1)?;
+        for (i, piece) in self.preview().enumerate() {
+            let pos = [
+                BOARD_WIDTH as i32 + 1,
+                PREVIEW_TOP + PREVIEW_SPACING * i as i32,
+            ];
+            piece.draw(ctx, pos, 1.0)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn fill_screen(&self, ctx: &mut Context, color: Color) -> GameResult<()> {
+        let rect = graphics::Rect::new(
+            0.0,
+            0.0,
+            UNIT * BOARD_WIDTH as f32,
+            UNIT * BOARD_HEIGHT as f32,
+        );
+        let mesh =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), rect, color).unwrap();
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    pub fn draw_centered(&self, ctx: &mut Context, text: &str, color: Color) -> GameResult<()> {
+        let mut score_text = graphics::Text::new(text);
+        score_text.set_font(graphics::Font::default(), graphics::Scale::uniform(36.0));
+
+        let text_width = score_text.width(ctx) as f32;
+        let screen_width = UNIT * BOARD_WIDTH as f32;
+        let width_padding = (screen_width - text_width) / 2.0;
+
+        let text_height = score_text.height(ctx) as f32;
+        let screen_height = UNIT * BOARD_HEIGHT as f32;
+        let height_padding = (screen_height - text_height) / 2.0;
+
+        graphics::draw(
+            ctx,
+            &score_text,
+            graphics::DrawParam::new()
+                .color(color)
+                .dest([width_padding, height_padding]),
+        )
+    }
+}
+
+impl Piece {
+    pub fn gen_mesh(&self, ctx: &mut Context) -> graphics::Mesh {
+        let color = self.color.to_color();
+
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        for &(x, y) in &self.shape {
+            let bounds =
+                graphics::Rect::new(UNIT * x as f32, UNIT * y as f32, UNIT - 1.0, UNIT - 1.0);
+            mesh_builder.rectangle(graphics::DrawMode::fill(), bounds, color);
+        }
+
+        mesh_builder.build(ctx).unwrap()
+    }
+
+    pub fn draw(&self, ctx: &mut Context, pos: [i32; 2], alpha: f32) -> GameResult<()> {
+        let mesh = self.gen_mesh(ctx);
+        let [x, y] = pos;
+        graphics::draw(
+            ctx,
+            &mesh,
+            graphics::DrawParam::new()
+                .dest([PIECE_SIZE * x as f32, PIECE_SIZE * y as f32])
+                .color(Color::new(1.0, 1.0, 1.0, alpha)),
+        )
+    }
+}
+
+impl PieceColor {
+    pub fn to_color(self) -> Color {
+        match self {
+            PieceColor::Teal => Color::from_rgb_u32(0x008080),
+            PieceColor::Blue => Color::from_rgb_u32(0x0341AE),
+            PieceColor::Orange => Color::from_rgb_u32(0xFF971C),
+            PieceColor::Yellow => Color::from_rgb_u32(0xFFD500),
+            PieceColor::Green => Color::from_rgb_u32(0x72CB3B),
+            PieceColor::Purple => Color::from_rgb_u32(0x800080),
+            PieceColor::Red => Color::from_rgb_u32(0xFF3213),
+            PieceColor::Empty => panic!(),
+        }
+    }
+}
+
+impl Replay {
+    /// Plays the recorded events back on `state`, drawing the game after
+    /// each of them.
+    pub fn run(
+        &self,
+        draw: DrawConfig,
+        ctx: &mut Context,
+        state: &mut TetrisState,
+    ) -> GameResult<()> {
+        for (frame, event) in self.events.iter().enumerate() {
+            event.apply(state);
+            if draw.draws(frame as u64 + 1) {
+                state.draw(ctx)?;
+            }
+        }
+
+        Ok(())
+    }
+}
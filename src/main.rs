@@ -7,18 +7,305 @@ use agent::*;
 mod misc;
 use misc::*;
 
-pub enum DrawConfig {
-    AllFrame,
-    NoFrames,
-    EveryNFrames(u64),
+mod cli;
+use cli::*;
+
+#[cfg(feature = "gui")]
+mod gui;
+#[cfg(feature = "gui")]
+use gui::*;
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+use std::time::Instant;
+use structopt::StructOpt;
+
+type CommandResult = Result<(), Box<dyn Error>>;
+
+fn main() -> CommandResult {
+    match Command::from_args() {
+        Command::Train(args) => train(args),
+        #[cfg(feature = "gui")]
+        Command::Play(args) => play(args),
+        #[cfg(feature = "gui")]
+        Command::Watch(args) => watch(args),
+        Command::Bench(args) => bench(args),
+        Command::Replay(args) => replay(args),
+    }
+}
+
+// Seed that every other generator of a command is derived from, drawn from
+// entropy unless given on the command line
+fn master_seed(seed: Option<u64>) -> u64 {
+    let seed = seed.unwrap_or_else(|| thread_rng().gen());
+    println!("Seed: {}", seed);
+    seed
 }
 
-// Only affects non-human agents
-const DRAW_CONFIG: DrawConfig = DrawConfig::AllFrame;
-// const DRAW_CONFIG: DrawConfig = DrawConfig::NoFrames;
-// const DRAW_CONFIG: DrawConfig = DrawConfig::EveryNFrames(1);
+fn thread_pool(threads: usize) -> Result<ThreadPool, Box<dyn Error>> {
+    Ok(rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()?)
+}
+
+fn train(args: TrainArgs) -> CommandResult {
+    let pool = thread_pool(args.threads)?;
+
+    let (agent, ruleset, training) = match &args.resume {
+        Some(path) => {
+            let trainer = checkpoint_trainer(path)?;
+            println!("Resuming {} training from {}", trainer, path.display());
+
+            match trainer.as_str() {
+                "genetic" => resume::<GeneticAlgorithm<_>>(&pool, &args, path)?,
+                "cma-es" => resume::<CmaEs<_>>(&pool, &args, path)?,
+                "cross-entropy" => resume::<CrossEntropy<_>>(&pool, &args, path)?,
+                "random-search" => resume::<RandomSearch<_>>(&pool, &args, path)?,
+                "hill-climbing" => resume::<HillClimbing<_>>(&pool, &args, path)?,
+                _ => return Err(format!("Unknown trainer: {}", trainer).into()),
+            }
+        }
+        None => start(&pool, &args)?,
+    };
 
-fn main() -> GameResult<()> {
+    println!("Weights after training:");
+    for (feature, w) in agent.features.iter().zip(&agent.weights) {
+        println!("-  {}: {}", feature.name, w);
+    }
+    println!("Average training score: {}", training.fitness);
+
+    let agent = agent.with_depth(args.search_depth);
+    SavedAgent::new(&agent, ruleset, Some(training)).save(&args.output)?;
+    println!("Saved agent to {}", args.output.display());
+
+    Ok(())
+}
+
+type Trained = (GeneticAgent, Ruleset, TrainingSummary);
+
+// Starts a new run with the hyperparameters in `args`
+fn start(pool: &ThreadPool, args: &TrainArgs) -> Result<Trained, Box<dyn Error>> {
+    let mut rng = SmallRng::seed_from_u64(master_seed(args.seed));
+
+    let eval = args.eval_config();
+    let ruleset = args.rules.ruleset();
+    let features = parse_features(&args.features).ok_or("Unknown feature set")?;
+    // Every trainer starts from random individuals like this one
+    let template = GeneticAgent::with_weights(features.clone(), vec![0.0; features.len()]);
+
+    println!("Training with hyperparameters:");
+    println!("-  trainer: {}", args.trainer);
+    println!("-  eval_iterations: {}", eval.iterations);
+    println!("-  common_seeds: {}", eval.common_seeds);
+    println!("-  aggregation: {:?}", eval.aggregation);
+    println!("-  max_pieces: {:?}", eval.max_pieces);
+    println!("-  time_limit: {:?}", eval.time_limit);
+    println!("-  randomizer: {}", ruleset.randomizer);
+    println!("-  preview_len: {}", ruleset.preview_len);
+    println!("-  features: {}", args.features);
+    println!("-  threads: {}", pool.current_num_threads());
+    println!("-  checkpoint: {:?}", args.checkpoint());
+
+    let rng = &mut rng;
+    match args.trainer.as_str() {
+        "genetic" => {
+            let config = args.genetic_config();
+            println!("-  num_generations: {}", config.num_generations);
+            println!("-  population_size: {}", config.population_size);
+            println!("-  selection_size: {}", config.selection_size);
+            println!("-  selection: {:?}", config.selection);
+            println!("-  elites: {}", config.elites);
+            println!("-  crossover: {:?}", config.crossover);
+            println!("-  mutation: {:?}", config.mutation);
+            println!("-  mutation_probability: {}", config.mutation_probability);
+            println!();
+
+            let optimizer = GeneticAlgorithm::new(&template, config, rng);
+            begin(pool, args, optimizer, config.num_generations, rng)
+        }
+        "cma-es" => {
+            let config = args.cmaes_config();
+            println!("-  num_generations: {}", config.num_generations);
+            println!("-  population_size: {}", config.population_size);
+            println!("-  step_size: {}", config.step_size);
+            println!("-  max_restarts: {}", config.max_restarts);
+            println!("-  stagnation: {}", config.stagnation);
+            println!();
+
+            let optimizer = CmaEs::new(&template, config, rng);
+            begin(pool, args, optimizer, config.num_generations, rng)
+        }
+        "cross-entropy" => {
+            let config = args.cem_config();
+            println!("-  num_generations: {}", config.num_generations);
+            println!("-  population_size: {}", config.population_size);
+            println!("-  elite_fraction: {}", config.elite_fraction);
+            println!("-  step_size: {}", config.step_size);
+            println!("-  noise: {}", config.noise);
+            println!("-  noise_decay: {}", config.noise_decay);
+            println!();
+
+            let optimizer = CrossEntropy::new(&template, config);
+            begin(pool, args, optimizer, config.num_generations, rng)
+        }
+        "random-search" => {
+            let config = args.search_config();
+            println!("-  num_generations: {}", config.num_generations);
+            println!("-  population_size: {}", config.population_size);
+            println!();
+
+            let optimizer = RandomSearch::new(&template, config);
+            begin(pool, args, optimizer, config.num_generations, rng)
+        }
+        "hill-climbing" => {
+            let config = args.search_config();
+            println!("-  num_generations: {}", config.num_generations);
+            println!("-  population_size: {}", config.population_size);
+            println!("-  mutation: {:?}", config.mutation);
+            println!();
+
+            let optimizer = HillClimbing::new(&template, config, rng);
+            begin(pool, args, optimizer, config.num_generations, rng)
+        }
+        _ => Err(format!("Unknown trainer: {}", args.trainer).into()),
+    }
+}
+
+// Trains with `optimizer` from its first generation
+fn begin<O>(
+    pool: &ThreadPool,
+    args: &TrainArgs,
+    optimizer: O,
+    num_generations: usize,
+    rng: &mut SmallRng,
+) -> Result<Trained, Box<dyn Error>>
+where
+    O: Optimizer<GeneticAgent> + Serialize + Send,
+{
+    let ruleset = args.rules.ruleset();
+    let eval = args.eval_config();
+    let training = Training::new(
+        &args.trainer,
+        ruleset,
+        optimizer,
+        eval,
+        num_generations,
+        rng,
+    );
+    finish(pool, args, training)
+}
+
+// Continues the run saved at `path`, whose optimizer is of type `O`
+fn resume<O>(pool: &ThreadPool, args: &TrainArgs, path: &Path) -> Result<Trained, Box<dyn Error>>
+where
+    O: Optimizer<GeneticAgent> + Serialize + DeserializeOwned + Send,
+{
+    let training = Training::<GeneticAgent, O>::load(path)?;
+    println!(
+        "-  generations: {} of {}",
+        training.generation, training.num_generations
+    );
+    println!();
+
+    finish(pool, args, training)
+}
+
+// Runs the remaining generations, saving a checkpoint after each one
+fn finish<O>(
+    pool: &ThreadPool,
+    args: &TrainArgs,
+    mut training: Training<GeneticAgent, O>,
+) -> Result<Trained, Box<dyn Error>>
+where
+    O: Optimizer<GeneticAgent> + Serialize + Send,
+{
+    let state = training
+        .ruleset
+        .new_state(0) // Cloned once per thread
+        .ok_or("Unknown randomizer")?;
+
+    let checkpoint = args.checkpoint();
+    let (agent, fitness) = pool
+        .install(|| training.run(&state, checkpoint))?
+        .ok_or("Trained for zero generations")?;
+
+    Ok((agent, training.ruleset.clone(), training.summary(fitness)))
+}
+
+// Weights of the agent given by `args`, or `None` for the random player,
+// along with the rules it plays under
+fn load_agent(args: &PlayerArgs) -> Result<(Option<GeneticAgent>, Ruleset), Box<dyn Error>> {
+    let (agent, ruleset) = match &args.reference {
+        Some(name) if name == "random" => {
+            println!("Playing the random reference player");
+            return Ok((None, args.rules.ruleset()));
+        }
+        Some(name) => {
+            let agent = GeneticAgent::reference(name)
+                .ok_or_else(|| format!("Unknown reference player: {}", name))?;
+            println!("Playing the {} reference player", name);
+            (agent, args.rules.ruleset())
+        }
+        None => {
+            let saved = SavedAgent::load(&args.agent)?;
+            println!("Loaded agent from {}", args.agent.display());
+            if let Some(training) = &saved.training {
+                println!(
+                    "Trained with {} for {} generations, scoring {}",
+                    training.trainer, training.generations, training.fitness
+                );
+            }
+            (saved.agent(), saved.ruleset)
+        }
+    };
+
+    println!("Weights:");
+    for (feature, w) in agent.features.iter().zip(&agent.weights) {
+        println!("-  {}: {}", feature.name, w);
+    }
+
+    print_search(&args.search, &agent);
+    Ok((Some(agent), ruleset))
+}
+
+// Agent playing with the weights of `evaluator`, or at random from `seed`
+fn player(args: &PlayerArgs, evaluator: &Option<GeneticAgent>, seed: u64) -> Box<dyn Agent> {
+    match evaluator {
+        Some(evaluator) => args.search.agent(evaluator),
+        None => Box::new(RandomAgent::new(seed)),
+    }
+}
+
+fn print_search(args: &SearchArgs, evaluator: &GeneticAgent) {
+    println!("Searching with:");
+    println!("-  search: {:?}", args.search);
+    println!("-  depth: {}", args.depth(evaluator));
+    if args.search != Search::Lookahead {
+        println!("-  width: {}", args.width());
+    }
+    if args.search == Search::Expectimax {
+        println!("-  min_probability: {}", args.min_probability);
+    }
+}
+
+#[cfg(feature = "gui")]
+fn save_replay(path: Option<&Path>, ruleset: Ruleset, state: &TetrisState) -> CommandResult {
+    if let Some(path) = path {
+        let replay = Replay::new(ruleset, state).ok_or("Game was not recorded")?;
+        replay.save(path)?;
+        println!("Saved replay to {}", path.display());
+    }
+
+    Ok(())
+}
+
+// Window fitting the board and a preview of `preview_len` pieces
+#[cfg(feature = "gui")]
+fn window(preview_len: usize) -> GameResult<(Context, EventsLoop)> {
     let config = conf::Conf {
         window_setup: conf::WindowSetup {
             title: "Genetic Tetris".to_string(),
@@ -35,137 +322,166 @@ fn main() -> GameResult<()> {
             min_height: 0.0,
             max_width: 0.0,
             min_width: 0.0,
-            width: PIECE_SIZE * BOARD_WIDTH as f32,
-            height: PIECE_SIZE * BOARD_HEIGHT as f32,
+            width: PIECE_SIZE * (BOARD_WIDTH + PANEL_WIDTH) as f32,
+            height: PIECE_SIZE * window_rows(preview_len) as f32,
             fullscreen_type: conf::FullscreenType::Windowed,
             maximized: false,
             resizable: false,
         },
     };
 
-    let (mut ctx, mut _events_loop) = ContextBuilder::new("GeneticTetris", "Dario Sucic")
+    ContextBuilder::new("GeneticTetris", "Dario Sucic")
         .conf(config)
         .build()
-        .unwrap();
+}
 
-    if let DrawConfig::NoFrames = DRAW_CONFIG {
-        drop(_events_loop);
-    }
+#[cfg(feature = "gui")]
+fn play(args: PlayArgs) -> CommandResult {
+    let ruleset = args.rules.ruleset();
+    let mut state = ruleset
+        .new_state(master_seed(args.seed))
+        .ok_or("Unknown randomizer")?;
+    state.record();
 
-    let eval_iterations = 5;
-    let num_generations = 3;
-    let population_size = 500;
-    let selection_size = population_size / 10;
-    let mutation_probability = 0.15;
+    let (mut ctx, events_loop) = window(state.preview_len)?;
+    HumanAgent::new(events_loop).run(&mut ctx, &mut state)?;
 
-    println!("Training with hyperparameters:");
-    println!("-  eval_iterations: {}", eval_iterations);
-    println!("-  num_generations: {}", num_generations);
-    println!("-  population_size: {}", population_size);
-    println!("-  selection_size: {}", selection_size);
-    println!("-  mutation_probability: {}", mutation_probability);
-    println!();
+    println!("Achieved Score: {}", state.score);
+    save_replay(args.record.as_deref(), ruleset, &state)
+}
 
-    let (mut agent, score) = GeneticAgent::from_genetic(
-        &mut ctx,
-        eval_iterations,
-        num_generations,
-        population_size,
-        selection_size,
-        mutation_probability,
-    )?;
+#[cfg(feature = "gui")]
+fn watch(args: WatchArgs) -> CommandResult {
+    let (evaluator, ruleset) = load_agent(&args.player)?;
+    let seed = master_seed(args.seed);
+    let mut agent = player(&args.player, &evaluator, seed);
 
-    println!("Weights after training: {:?}", agent.weights);
-    println!("Average training score: {}", score);
+    let mut state = ruleset
+        .new_state(seed)
+        .ok_or("Agent plays under rules this build cannot play")?;
+    state.record();
 
-    let mut state = TetrisState::new(&mut ctx);
-    agent.run(DRAW_CONFIG, &mut ctx, &mut state)?;
+    let (mut ctx, events_loop) = window(state.preview_len)?;
+    if let DrawConfig::NoFrames = args.draw {
+        drop(events_loop);
+    }
+    agent.run(args.draw, &mut ctx, &mut state)?;
 
     println!("Achieved Score: {}", state.score);
+    save_replay(args.record.as_deref(), ruleset, &state)
+}
+
+fn bench(args: BenchArgs) -> CommandResult {
+    let (evaluator, ruleset) = load_agent(&args.player)?;
+    let state = ruleset
+        .new_state(0) // Cloned once per thread
+        .ok_or("Agent plays under rules this build cannot play")?;
+
+    let mut rng = SmallRng::seed_from_u64(master_seed(args.seed));
+    let seeds = (0..args.games).map(|_| rng.gen()).collect::<Vec<u64>>();
+
+    let eval = EvalConfig {
+        iterations: 1,
+        common_seeds: false,
+        aggregation: Aggregation::Mean,
+        max_pieces: Some(args.max_pieces).filter(|&n| n > 0),
+        time_limit: None,
+    };
+
+    let pool = thread_pool(args.threads)?;
+    let start = Instant::now();
+    let games = pool.install(|| {
+        seeds
+            .par_iter()
+            .map_init(
+                || state.clone(),
+                |state, &seed| {
+                    let mut agent = player(&args.player, &evaluator, seed);
+                    state.reset(seed);
+                    agent.play_limited(state, &eval);
+                    (state.score as f64, state.pieces)
+                },
+            )
+            .collect::<Vec<(f64, usize)>>()
+    });
+    let elapsed = start.elapsed();
+
+    let scores = games.iter().map(|&(score, _)| score).collect::<Vec<f64>>();
+    let pieces = games.iter().map(|&(_, pieces)| pieces).sum::<usize>();
+    let mean = Aggregation::Mean.aggregate(&scores);
+    let std_dev = if scores.len() > 1 {
+        let variance =
+            scores.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (scores.len() - 1) as f64;
+        variance.sqrt()
+    } else {
+        0.0
+    };
+    let max = scores.iter().cloned().fold(0.0, f64::max);
+
+    println!("Games: {}", scores.len());
+    println!("-  mean score: {:.1} (std dev {:.1})", mean, std_dev);
+    println!(
+        "-  median score: {}",
+        Aggregation::Median.aggregate(&scores)
+    );
+    println!("-  min score: {}", Aggregation::Min.aggregate(&scores));
+    println!("-  max score: {}", max);
+    println!(
+        "-  mean pieces: {:.1}",
+        pieces as f64 / scores.len().max(1) as f64
+    );
+    if let Some(cap) = eval.max_pieces {
+        let capped = games.iter().filter(|&&(_, n)| n >= cap).count();
+        println!("-  games cut short: {}", capped);
+    }
+    println!(
+        "Played in {:.2?}, {:.0} pieces per second",
+        elapsed,
+        pieces as f64 / elapsed.as_secs_f64()
+    );
 
     Ok(())
 }
 
-impl EventHandler for TetrisState {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if self.is_over || !self.is_valid_move(self.pos, &self.current_piece) {
-            self.is_over = true;
-            return Ok(());
-        }
+fn replay(args: ReplayArgs) -> CommandResult {
+    let replay = Replay::load(&args.replay)?;
+    let mut state = replay
+        .new_state()
+        .ok_or("Replay was recorded under rules this build cannot play")?;
 
-        self.drop_count += 1;
-        self.sub_count += 1;
+    #[cfg(feature = "gui")]
+    {
+        let (mut ctx, events_loop) = window(state.preview_len)?;
+        if let DrawConfig::NoFrames = args.draw {
+            drop(events_loop);
+        }
+        replay.run(args.draw, &mut ctx, &mut state)?;
+    }
+    #[cfg(not(feature = "gui"))]
+    for event in &replay.events {
+        event.apply(&mut state);
+    }
 
-        let per_second = self.drop_count % 144 == 0;
-        let per_sub = self.sub_count % 24 == 0;
+    println!("Achieved Score: {}", state.score);
+    if state.score != replay.score {
+        return Err(format!("Playback diverged, the recorded score is {}", replay.score).into());
+    }
 
-        if per_sub {
-            self.pressed_map.insert(KeyCode::Left, false);
-            self.pressed_map.insert(KeyCode::Right, false);
-            if !per_second {
-                self.pressed_map.insert(KeyCode::Down, false);
-            }
-        }
+    Ok(())
+}
 
-        if per_second {
-            let mut new_pos = self.pos;
-            new_pos[1] += 1;
-
-            if self.is_valid_move(new_pos, &self.current_piece) {
-                self.pos = new_pos;
-            } else {
-                self.lock_current_piece();
-                self.current_piece = self.next_piece;
-                self.next_piece = Piece::random(&mut self.rng);
-                self.pos = [BOARD_WIDTH as i32 / 2 - 2, PIECE_SPAWN_OFFSET];
-                self.propagate_lines();
-                self.score += 1;
-            }
+#[cfg(feature = "gui")]
+impl EventHandler for Frontend<'_> {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.state.tick();
+        if !self.state.is_over {
+            self.input_handler(ctx);
         }
 
-        self.rotation_handler(ctx);
-        self.movement_handler(ctx);
-        self.drop_handler(ctx);
-        self.update_ghost_pos();
-
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx, graphics::WHITE);
-
-        let rect = graphics::Rect::new(
-            0.0,
-            UNIT * PIECE_SPAWN_OFFSET as f32,
-            UNIT * BOARD_WIDTH as f32,
-            1.0,
-        );
-
-        let mesh = graphics::Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            rect,
-            Color::new(0.6, 0.0, 0.0, 1.0),
-        )
-        .unwrap();
-
-        graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
-        self.current_piece.draw(ctx, self.pos, 1.0)?;
-        self.current_piece.draw(ctx, self.ghost_pos, 0.25)?;
-        self.draw_map(ctx)?;
-
-        let score_text = graphics::Text::new(format!("Score: {}", self.score));
-        graphics::draw(
-            ctx,
-            &score_text,
-            graphics::DrawParam::new().color(Color::from_rgb(0, 0, 0)),
-        )?;
-
-        if self.is_over {
-            self.fill_screen(ctx, Color::new(0.0, 0.0, 0.0, 0.9))?;
-            self.draw_centered(ctx, "Game Over!", Color::new(1.0, 0.3, 0.3, 1.0))?;
-        }
-
-        graphics::present(ctx)
+        self.state.draw(ctx)
     }
 }
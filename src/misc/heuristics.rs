@@ -1,6 +1,12 @@
+// Board features read most naturally as row/column index loops
+#![allow(clippy::needless_range_loop)]
+
 use crate::*;
 
-pub fn surface_roughness_heuristic(board: &TetrisBoard) -> f64 {
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn surface_roughness_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
     let mut prev = BOARD_HEIGHT as i32;
     let mut total: i32 = 0;
 
@@ -27,7 +33,7 @@ pub fn surface_roughness_heuristic(board: &TetrisBoard) -> f64 {
     total as f64
 }
 
-pub fn height_heuristic(board: &TetrisBoard) -> f64 {
+pub fn height_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
     for i in 0..BOARD_HEIGHT {
         for j in 0..BOARD_WIDTH {
             if board[i][j] != PieceColor::Empty {
@@ -38,15 +44,11 @@ pub fn height_heuristic(board: &TetrisBoard) -> f64 {
     0.0
 }
 
-pub fn line_completion_heuristic(board: &TetrisBoard) -> f64 {
-    let mut n_lines = 0;
-    for i in 0..BOARD_HEIGHT {
-        n_lines += board[i].iter().all(|&v| v != PieceColor::Empty) as i32;
-    }
-    n_lines as f64
+pub fn line_completion_heuristic(_board: &TetrisBoard, info: &PlacementInfo) -> f64 {
+    info.lines_cleared as f64
 }
 
-pub fn ceil_gap_heuristic(board: &TetrisBoard) -> f64 {
+pub fn ceil_gap_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
     let mut total = 0;
     for i in 0..BOARD_HEIGHT - 1 {
         for j in 0..BOARD_WIDTH {
@@ -63,10 +65,402 @@ pub fn ceil_gap_heuristic(board: &TetrisBoard) -> f64 {
     total as f64
 }
 
-pub const N_HEURISTICS: usize = 4;
-pub const HEURISTICS: [fn(&TetrisBoard) -> f64; N_HEURISTICS] = [
-    surface_roughness_heuristic,
-    height_heuristic,
-    line_completion_heuristic,
-    ceil_gap_heuristic,
+pub fn landing_height_heuristic(_board: &TetrisBoard, info: &PlacementInfo) -> f64 {
+    info.landing_height
+}
+
+// Lines cleared times the number of cells the piece contributed to them
+pub fn eroded_piece_cells_heuristic(_board: &TetrisBoard, info: &PlacementInfo) -> f64 {
+    (info.lines_cleared * info.eroded_cells) as f64
+}
+
+// Walls count as filled cells
+fn is_filled(board: &TetrisBoard, x: i32, y: usize) -> bool {
+    x < 0 || x >= BOARD_WIDTH as i32 || board[y][x as usize] != PieceColor::Empty
+}
+
+// Changes between filled and empty cells along each row, walls included
+pub fn row_transitions_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
+    let mut total = 0;
+    for i in 0..BOARD_HEIGHT {
+        for j in 0..=BOARD_WIDTH as i32 {
+            total += (is_filled(board, j - 1, i) != is_filled(board, j, i)) as i32;
+        }
+    }
+    total as f64
+}
+
+// Changes between filled and empty cells down each column, with the floor
+// counting as filled
+pub fn column_transitions_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
+    let mut total = 0;
+    for j in 0..BOARD_WIDTH {
+        let mut prev = false;
+        for i in 0..BOARD_HEIGHT {
+            let curr = board[i][j] != PieceColor::Empty;
+            total += (prev != curr) as i32;
+            prev = curr;
+        }
+        total += !prev as i32;
+    }
+    total as f64
+}
+
+// Empty cells with at least one filled cell somewhere above them
+pub fn holes_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
+    let mut total = 0;
+    for j in 0..BOARD_WIDTH {
+        let mut covered = false;
+        for i in 0..BOARD_HEIGHT {
+            if board[i][j] != PieceColor::Empty {
+                covered = true;
+            } else if covered {
+                total += 1;
+            }
+        }
+    }
+    total as f64
+}
+
+// Each well cell, empty with filled cells or walls on both sides, counts
+// for its depth within the well, so a well of depth n adds 1 + 2 + ... + n
+pub fn cumulative_wells_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
+    let mut total = 0;
+    for j in 0..BOARD_WIDTH {
+        let mut depth = 0;
+        for i in 0..BOARD_HEIGHT {
+            let x = j as i32;
+            if board[i][j] == PieceColor::Empty
+                && is_filled(board, x - 1, i)
+                && is_filled(board, x + 1, i)
+            {
+                depth += 1;
+                total += depth;
+            } else {
+                depth = 0;
+            }
+        }
+    }
+    total as f64
+}
+
+// Filled cells stacked above each hole in its column
+pub fn hole_depth_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
+    let mut total = 0;
+    for j in 0..BOARD_WIDTH {
+        let mut above = 0;
+        for i in 0..BOARD_HEIGHT {
+            if board[i][j] != PieceColor::Empty {
+                above += 1;
+            } else {
+                total += above;
+            }
+        }
+    }
+    total as f64
+}
+
+pub fn rows_with_holes_heuristic(board: &TetrisBoard, _info: &PlacementInfo) -> f64 {
+    let mut covered = [false; BOARD_WIDTH];
+    let mut total = 0;
+    for i in 0..BOARD_HEIGHT {
+        let mut has_hole = false;
+        for j in 0..BOARD_WIDTH {
+            if board[i][j] != PieceColor::Empty {
+                covered[j] = true;
+            } else if covered[j] {
+                has_hole = true;
+            }
+        }
+        total += has_hole as i32;
+    }
+    total as f64
+}
+
+// Boards are scored after lines are cleared, with what happened during the
+// placement passed alongside
+pub type Heuristic = fn(&TetrisBoard, &PlacementInfo) -> f64;
+
+/// A heuristic along with the name it is selected by.
+#[derive(Clone, Copy)]
+pub struct Feature {
+    pub name: &'static str,
+    pub function: Heuristic,
+}
+
+impl std::fmt::Debug for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+// Every heuristic that feature sets can be built from
+pub const HEURISTICS: [Feature; 12] = [
+    Feature {
+        name: "surface-roughness",
+        function: surface_roughness_heuristic,
+    },
+    Feature {
+        name: "height",
+        function: height_heuristic,
+    },
+    Feature {
+        name: "line-completion",
+        function: line_completion_heuristic,
+    },
+    Feature {
+        name: "ceil-gap",
+        function: ceil_gap_heuristic,
+    },
+    Feature {
+        name: "landing-height",
+        function: landing_height_heuristic,
+    },
+    Feature {
+        name: "eroded-piece-cells",
+        function: eroded_piece_cells_heuristic,
+    },
+    Feature {
+        name: "row-transitions",
+        function: row_transitions_heuristic,
+    },
+    Feature {
+        name: "column-transitions",
+        function: column_transitions_heuristic,
+    },
+    Feature {
+        name: "holes",
+        function: holes_heuristic,
+    },
+    Feature {
+        name: "cumulative-wells",
+        function: cumulative_wells_heuristic,
+    },
+    Feature {
+        name: "hole-depth",
+        function: hole_depth_heuristic,
+    },
+    Feature {
+        name: "rows-with-holes",
+        function: rows_with_holes_heuristic,
+    },
+];
+
+// Feature sets that can be selected by a single name
+const PRESETS: [(&str, &[&str]); 3] = [
+    (
+        "basic",
+        &["surface-roughness", "height", "line-completion", "ceil-gap"],
+    ),
+    (
+        "dellacherie",
+        &[
+            "landing-height",
+            "eroded-piece-cells",
+            "row-transitions",
+            "column-transitions",
+            "holes",
+            "cumulative-wells",
+        ],
+    ),
+    (
+        "bcts",
+        &[
+            "landing-height",
+            "eroded-piece-cells",
+            "row-transitions",
+            "column-transitions",
+            "holes",
+            "cumulative-wells",
+            "hole-depth",
+            "rows-with-holes",
+        ],
+    ),
 ];
+
+pub fn find_heuristic(name: &str) -> Option<Feature> {
+    HEURISTICS
+        .iter()
+        .find(|feature| feature.name == name)
+        .copied()
+}
+
+/// Ordered selection of heuristics. Agents keep one weight per feature, in
+/// the same order.
+#[derive(Debug, Clone)]
+pub struct FeatureSet {
+    features: Vec<Feature>,
+}
+
+impl FeatureSet {
+    pub fn new(features: Vec<Feature>) -> FeatureSet {
+        FeatureSet { features }
+    }
+
+    pub fn all() -> FeatureSet {
+        FeatureSet::new(HEURISTICS.to_vec())
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Feature> {
+        self.features.iter()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.features.iter().map(|feature| feature.name).collect()
+    }
+
+    /// Weighted sum of every feature on the given board.
+    pub fn dot(&self, weights: &[f64], board: &TetrisBoard, info: &PlacementInfo) -> f64 {
+        self.features
+            .iter()
+            .zip(weights)
+            .fold(0.0, |acc, (feature, w)| {
+                acc + w * (feature.function)(board, info)
+            })
+    }
+}
+
+// Feature sets are the same when they list the same heuristics in the same
+// order, which is when their weights can be mixed
+impl PartialEq for FeatureSet {
+    fn eq(&self, other: &FeatureSet) -> bool {
+        self.names() == other.names()
+    }
+}
+
+// Feature sets are stored by the names of their heuristics, in order
+impl Serialize for FeatureSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.names().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FeatureSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FeatureSet, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        parse_features(&names.join(","))
+            .ok_or_else(|| D::Error::custom(format!("Invalid feature set: {}", names.join(","))))
+    }
+}
+
+/// Builds a feature set from `all`, a preset (`basic`, `dellacherie` or
+/// `bcts`), or a comma-separated list of heuristic names such as
+/// `holes,height,row-transitions`.
+pub fn parse_features(spec: &str) -> Option<FeatureSet> {
+    let spec = spec.to_lowercase();
+
+    if spec == "all" {
+        return Some(FeatureSet::all());
+    }
+
+    let names = match PRESETS.iter().find(|(name, _)| *name == spec) {
+        Some((_, names)) => names.to_vec(),
+        None => spec.split(',').map(str::trim).collect(),
+    };
+
+    let mut features = Vec::with_capacity(names.len());
+    for name in names {
+        let feature = find_heuristic(name)?;
+        if features.iter().any(|f: &Feature| f.name == feature.name) {
+            return None;
+        }
+        features.push(feature);
+    }
+
+    Some(FeatureSet::new(features))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bottom two rows filled except for the rightmost column, leaving a well
+    // two cells deep against the wall
+    fn well_board() -> TetrisBoard {
+        let mut board = [[PieceColor::Empty; BOARD_WIDTH]; BOARD_HEIGHT];
+        for row in &mut board[BOARD_HEIGHT - 2..] {
+            for cell in &mut row[..BOARD_WIDTH - 1] {
+                *cell = PieceColor::Blue;
+            }
+        }
+        board
+    }
+
+    fn no_info() -> PlacementInfo {
+        PlacementInfo {
+            lines_cleared: 0,
+            eroded_cells: 0,
+            landing_height: 0.0,
+        }
+    }
+
+    #[test]
+    fn walls_and_floor_count_as_filled() {
+        let info = no_info();
+        let mut board = [[PieceColor::Empty; BOARD_WIDTH]; BOARD_HEIGHT];
+
+        // Every empty row changes at both walls, and every empty column
+        // changes at the floor
+        assert_eq!(row_transitions_heuristic(&board, &info), 40.0);
+        assert_eq!(column_transitions_heuristic(&board, &info), 10.0);
+
+        // A full row touches both walls without changing
+        board[BOARD_HEIGHT - 1] = [PieceColor::Blue; BOARD_WIDTH];
+        assert_eq!(row_transitions_heuristic(&board, &info), 38.0);
+        assert_eq!(column_transitions_heuristic(&board, &info), 10.0);
+
+        let board = well_board();
+        assert_eq!(row_transitions_heuristic(&board, &info), 40.0);
+        assert_eq!(column_transitions_heuristic(&board, &info), 10.0);
+        assert_eq!(cumulative_wells_heuristic(&board, &info), 3.0);
+        assert_eq!(holes_heuristic(&board, &info), 0.0);
+    }
+
+    #[test]
+    fn covered_cells_still_count_as_wells() {
+        let info = no_info();
+        let mut board = well_board();
+        board[BOARD_HEIGHT - 3][BOARD_WIDTH - 1] = PieceColor::Blue;
+
+        assert_eq!(cumulative_wells_heuristic(&board, &info), 3.0);
+        assert_eq!(holes_heuristic(&board, &info), 2.0);
+        assert_eq!(hole_depth_heuristic(&board, &info), 2.0);
+        assert_eq!(rows_with_holes_heuristic(&board, &info), 2.0);
+        assert_eq!(row_transitions_heuristic(&board, &info), 40.0);
+        assert_eq!(column_transitions_heuristic(&board, &info), 12.0);
+    }
+
+    #[test]
+    fn landing_height_is_the_middle_of_the_piece() {
+        let mut state = TetrisState::new(Box::new(Uniform), 0, 0);
+        let placement = |kind, rotation, x, y| Placement {
+            kind,
+            hold: false,
+            rotation,
+            x,
+            y,
+            path: vec![],
+        };
+
+        let o = placement(PieceKind::O, Orientation::Spawn, 3, 18);
+        let (_, info) = state.resolve_placement(&o);
+        assert_eq!(landing_height_heuristic(&state.board, &info), 1.5);
+
+        state.board = well_board();
+        let o = placement(PieceKind::O, Orientation::Spawn, 3, 16);
+        let (_, info) = state.resolve_placement(&o);
+        assert_eq!(landing_height_heuristic(&state.board, &info), 3.5);
+
+        // Upright I filling the well, measured before the two lines clear
+        let i = placement(PieceKind::I, Orientation::Right, 7, 16);
+        let (board, info) = state.resolve_placement(&i);
+        assert_eq!(landing_height_heuristic(&board, &info), 2.5);
+        assert_eq!(line_completion_heuristic(&board, &info), 2.0);
+        assert_eq!(eroded_piece_cells_heuristic(&board, &info), 4.0);
+        assert_eq!(height_heuristic(&board, &info), 2.0);
+    }
+}
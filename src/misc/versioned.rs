@@ -0,0 +1,24 @@
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Loads the JSON file at `path`, after checking that its `version` field is
+/// the one this build writes. `kind` names the file in error messages.
+pub fn load_versioned<T: DeserializeOwned>(path: &Path, kind: &str, version: u32) -> io::Result<T> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let value: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let found = value
+        .get("version")
+        .and_then(|version| version.as_u64())
+        .ok_or_else(|| invalid(format!("Missing {} file version", kind)))?;
+    if found != version as u64 {
+        return Err(invalid(format!(
+            "Unsupported {} file version {}, expected {}",
+            kind, found, version
+        )));
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
@@ -1,180 +1,202 @@
-pub use ggez::event::{self, EventHandler};
-pub use ggez::{
-    conf,
-    graphics::{self, Color},
-    input::keyboard::{is_key_pressed, KeyCode},
-    Context, ContextBuilder, GameResult,
-};
-
 pub use rand::prelude::*;
 pub use rand::{rngs::SmallRng, Rng};
 
-pub use std::collections::HashMap;
+pub use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
 
-pub const PIECE_SIZE: f32 = 30.0;
-pub const UNIT: f32 = PIECE_SIZE;
 pub const PIECE_SPAWN_OFFSET: i32 = 2;
 pub const BOARD_WIDTH: usize = 10;
 pub const BOARD_HEIGHT: usize = 20;
+pub const SPAWN_POS: [i32; 2] = [BOARD_WIDTH as i32 / 2 - 2, PIECE_SPAWN_OFFSET];
+
+// Number of ticks between two gravity steps
+pub const TICKS_PER_DROP: u64 = 144;
 
 mod piece;
 pub use piece::*;
 
+mod placement;
+pub use placement::*;
+
+mod randomizer;
+pub use randomizer::*;
+
+mod ruleset;
+pub use ruleset::Ruleset;
+
+mod replay;
+pub use replay::{Event, Recording, Replay};
+
 pub type TetrisBoard = [[PieceColor; BOARD_WIDTH]; BOARD_HEIGHT];
 
+/// A single player input, independent of whatever device produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Input {
+    Left,
+    Right,
+    Down,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    Drop,
+    Hold,
+}
+
 #[derive(Clone)]
 pub struct TetrisState {
     pub current_piece: Piece,
     pub ghost_pos: [i32; 2],
-    pub next_piece: Piece,
+    // Upcoming pieces, visible to both players and agents
+    pub queue: VecDeque<Piece>,
+    pub preview_len: usize,
+    pub hold: Option<Piece>,
+    // Holding is allowed once per piece
+    pub can_hold: bool,
     pub pos: [i32; 2],
     pub board: TetrisBoard,
     pub drop_count: u64,
-    pub sub_count: u64,
-    pub pressed_map: HashMap<KeyCode, bool>,
     pub rng: SmallRng,
+    pub seed: u64,
+    pub randomizer: Box<dyn Randomizer>,
     pub score: u32,
+    // Pieces locked since the start of the game
+    pub pieces: usize,
     pub is_over: bool,
+    // Off unless started with `record`
+    pub recording: Recording,
 }
 
 impl TetrisState {
-    pub fn new(_ctx: &mut Context) -> TetrisState {
-        let mut rng = SmallRng::from_entropy();
-
-        let mut pressed_map = HashMap::new();
-        for &key in &[KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down] {
-            pressed_map.insert(key, false);
-        }
-
-        TetrisState {
-            current_piece: Piece::random(&mut rng),
-            next_piece: Piece::random(&mut rng),
-            pos: [BOARD_WIDTH as i32 / 2 - 2, PIECE_SPAWN_OFFSET],
+    /// Creates a game whose piece sequence is entirely determined by the
+    /// randomizer and `seed`, showing `preview_len` upcoming pieces.
+    pub fn new(randomizer: Box<dyn Randomizer>, preview_len: usize, seed: u64) -> TetrisState {
+        let mut state = TetrisState {
+            current_piece: PIECES[0],
+            queue: VecDeque::with_capacity(preview_len),
+            preview_len,
+            hold: None,
+            can_hold: true,
+            pos: SPAWN_POS,
             ghost_pos: [0, 0],
             board: [[PieceColor::Empty; BOARD_WIDTH]; BOARD_HEIGHT],
             drop_count: 0,
-            sub_count: 0,
-            pressed_map,
-            rng,
+            rng: SmallRng::seed_from_u64(seed),
+            seed,
+            randomizer,
             score: 0,
+            pieces: 0,
             is_over: false,
-        }
+            recording: Recording::default(),
+        };
+
+        state.reset(seed);
+        state
     }
 
-    pub fn reset(&mut self) {
-        self.rng = SmallRng::from_entropy();
-        self.current_piece = Piece::random(&mut self.rng);
-        self.next_piece = Piece::random(&mut self.rng);
-        self.pos = [BOARD_WIDTH as i32 / 2 - 2, PIECE_SPAWN_OFFSET];
+    pub fn reset(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self.seed = seed;
+        self.randomizer.reset();
+        self.queue.clear();
+        self.hold = None;
+        self.can_hold = true;
+        self.current_piece = self.pop_next_piece();
+        self.pos = SPAWN_POS;
         self.ghost_pos = [0, 0];
         self.board
             .iter_mut()
             .for_each(|line| line.iter_mut().for_each(|x| *x = PieceColor::Empty));
         self.drop_count = 0;
-        self.sub_count = 0;
-        self.pressed_map.clear();
         self.score = 0;
+        self.pieces = 0;
         self.is_over = false;
+        self.recording.clear();
     }
 
-    pub fn pick_move_by_key<F>(&mut self, f: F) -> Option<KeyCode>
-    where
-        F: Fn(&TetrisBoard) -> f64,
-    {
-        fn with_locked(mut board: TetrisBoard, pos: [i32; 2], piece: &Piece) -> TetrisBoard {
-            let color = piece.color;
-            let [px, py] = pos;
-            for &(x, y) in &piece.shape {
-                let x = px + x as i32;
-                let y = py + y as i32;
-                board[y as usize][x as usize] = color;
-            }
-            board
+    /// Advances the simulation by one tick, applying gravity and locking the
+    /// current piece once it can no longer fall.
+    pub fn tick(&mut self) {
+        if self.is_over {
+            return;
         }
 
-        let mut piece = self.current_piece;
-        let mut pos = self.pos;
-        let mut best = (1e9, 0, self.pos);
-
-        let xmax = BOARD_WIDTH as i32;
-        for rotation in 0..4 {
-            let (p_xmin, p_xmax) = piece.x_bounds();
-            for x in 0 - p_xmin..xmax - p_xmax {
-                pos[0] = x;
-                if !self.is_valid_move(pos, &piece) {
-                    continue;
-                }
-                let drop = self.calc_drop_pos(pos, &piece);
-                let board = with_locked(self.board, drop, &piece);
-                let loss = f(&board);
-                // println!("[x, rot] = {:?} => {:.2}", [x, rotation], loss);
-                if loss < best.0 {
-                    best = (loss, rotation, pos);
-                }
-            }
-
-            if rotation < 3 {
-                piece.rotate();
-            }
+        self.recording.push(Event::Tick);
+        if !self.is_valid_move(self.pos, &self.current_piece) {
+            self.is_over = true;
+            return;
         }
 
-        let (_best_score, best_rotation, best_pos) = best;
+        self.drop_count += 1;
 
-        if best_rotation > 0 {
-            return Some(KeyCode::Up);
-        }
+        if self.drop_count.is_multiple_of(TICKS_PER_DROP) {
+            let mut new_pos = self.pos;
+            new_pos[1] += 1;
 
-        use std::cmp::Ordering;
-        match best_pos[0].cmp(&self.pos[0]) {
-            Ordering::Equal => None,
-            Ordering::Less => Some(KeyCode::Left),
-            Ordering::Greater => Some(KeyCode::Right),
+            if self.is_valid_move(new_pos, &self.current_piece) {
+                self.pos = new_pos;
+            } else {
+                self.lock_and_spawn();
+            }
         }
+
+        self.update_ghost_pos();
     }
 
-    pub fn key_handler<F>(&mut self, ctx: &mut Context, key: KeyCode, mut handler: F)
-    where
-        F: FnMut(&mut TetrisState),
-    {
-        let is_pressed = self.pressed_map.entry(key).or_default();
-
-        if is_key_pressed(ctx, key) {
-            if !*is_pressed {
-                *is_pressed = true;
-                handler(self);
-                self.sub_count = 0;
-            }
-        } else {
-            *is_pressed = false;
+    pub fn apply(&mut self, input: Input) {
+        if self.is_over {
+            return;
         }
-    }
 
-    pub fn rotate_current_piece(&mut self) {
-        let mut new_piece = self.current_piece;
-        new_piece.rotate();
+        self.recording.push(Event::Input(input));
 
-        if self.is_valid_move(self.pos, &new_piece) {
-            self.current_piece = new_piece;
+        if let Input::Drop = input {
+            // Land the piece and let the next tick lock it
+            self.pos = self.calc_drop_pos(self.pos, &self.current_piece);
+            self.drop_count += TICKS_PER_DROP - self.drop_count % TICKS_PER_DROP - 1;
+        } else if let Input::Hold = input {
+            self.hold_current_piece();
+        } else if let Some((piece, pos)) = self.try_input(&self.current_piece, self.pos, input) {
+            self.current_piece = piece;
+            self.pos = pos;
         }
-    }
 
-    pub fn movement_handler(&mut self, ctx: &mut Context) {
-        self.key_handler(ctx, KeyCode::Left, |state| state.move_current_piece(-1, 0));
-        self.key_handler(ctx, KeyCode::Right, |state| state.move_current_piece(1, 0));
-        self.key_handler(ctx, KeyCode::Down, |state| state.move_current_piece(0, 1));
+        self.update_ghost_pos();
     }
 
-    pub fn rotation_handler(&mut self, ctx: &mut Context) {
-        self.key_handler(ctx, KeyCode::Up, |state| {
-            state.rotate_current_piece();
-        });
+    /// Returns where `piece` ends up after a movement or rotation input, or
+    /// `None` if the input is blocked. Rotations go through the SRS kicks.
+    pub fn try_input(
+        &self,
+        piece: &Piece,
+        pos: [i32; 2],
+        input: Input,
+    ) -> Option<(Piece, [i32; 2])> {
+        let rotation = match input {
+            Input::Left => return self.try_move(piece, [pos[0] - 1, pos[1]]),
+            Input::Right => return self.try_move(piece, [pos[0] + 1, pos[1]]),
+            Input::Down => return self.try_move(piece, [pos[0], pos[1] + 1]),
+            Input::Drop => return Some((*piece, self.calc_drop_pos(pos, piece))),
+            Input::Hold => return None,
+            Input::RotateCw => Rotation::Clockwise,
+            Input::RotateCcw => Rotation::CounterClockwise,
+            Input::Rotate180 => Rotation::Half,
+        };
+
+        let rotated = piece.rotated(rotation);
+        piece
+            .kicks(rotation)
+            .map(|(dx, dy)| [pos[0] + dx, pos[1] + dy])
+            .find(|&pos| self.is_valid_move(pos, &rotated))
+            .map(|pos| (rotated, pos))
     }
 
-    pub fn drop_handler(&mut self, ctx: &mut Context) {
-        self.key_handler(ctx, KeyCode::Space, |state| {
-            state.pos = state.calc_drop_pos(state.pos, &state.current_piece);
-            state.drop_count += 144 - state.drop_count % 144 - 1;
-        });
+    fn try_move(&self, piece: &Piece, pos: [i32; 2]) -> Option<(Piece, [i32; 2])> {
+        if self.is_valid_move(pos, piece) {
+            Some((*piece, pos))
+        } else {
+            None
+        }
     }
 
     pub fn collides(&self, x: usize, y: usize) -> bool {
@@ -199,66 +221,85 @@ impl TetrisState {
         true
     }
 
-    pub fn move_current_piece(&mut self, dx: i32, dy: i32) {
-        let pos = [self.pos[0] + dx, self.pos[1] + dy];
-        if self.is_valid_move(pos, &self.current_piece) {
-            self.pos = pos;
-        }
-    }
-
     pub fn lock_current_piece(&mut self) {
         let color = self.current_piece.color;
-        for &(x, y) in &self.current_piece.shape {
-            let x = self.pos[0] + x as i32;
-            let y = self.pos[1] + y as i32;
+        for (x, y) in self.current_piece.cells(self.pos) {
             self.board[y as usize][x as usize] = color;
         }
     }
 
-    pub fn propagate_lines(&mut self) {
-        for y in 0..BOARD_HEIGHT {
-            if self.board[y].iter().all(|&v| v != PieceColor::Empty) {
-                self.score += 100;
-                for x in 0..BOARD_WIDTH {
-                    self.board[y][x] = PieceColor::Empty;
-                }
-                self.board[0..=y].rotate_right(1);
-            }
+    /// Locks the current piece in place, clears any completed lines and
+    /// brings in the next piece.
+    pub fn lock_and_spawn(&mut self) {
+        self.lock_current_piece();
+        self.current_piece = self.pop_next_piece();
+        self.pos = SPAWN_POS;
+        self.can_hold = true;
+        self.propagate_lines();
+        self.score += 1;
+        self.pieces += 1;
+
+        if !self.is_valid_move(self.pos, &self.current_piece) {
+            self.is_over = true;
         }
     }
 
-    pub fn draw_map(&self, ctx: &mut Context) -> GameResult<()> {
-        let mut mesh_builder = graphics::MeshBuilder::new();
+    /// Swaps the current piece with the held one, or with the next piece if
+    /// nothing is held yet. Does nothing if the current piece was itself
+    /// swapped in by a hold.
+    pub fn hold_current_piece(&mut self) {
+        if !self.can_hold {
+            return;
+        }
 
-        let mut n = 0;
+        let held = Piece::new(self.current_piece.kind);
+        self.current_piece = match self.hold.replace(held) {
+            Some(piece) => piece,
+            None => self.pop_next_piece(),
+        };
+        self.pos = SPAWN_POS;
+        self.can_hold = false;
 
-        for y in 0..BOARD_HEIGHT {
-            for x in 0..BOARD_WIDTH {
-                let piece_color = self.board[y][x];
-                if piece_color == PieceColor::Empty {
-                    continue;
-                }
-                let color = piece_color.to_color();
+        if !self.is_valid_move(self.pos, &self.current_piece) {
+            self.is_over = true;
+        }
+    }
 
-                let bounds =
-                    graphics::Rect::new(UNIT * x as f32, UNIT * y as f32, UNIT - 1.0, UNIT - 1.0);
-                mesh_builder.rectangle(graphics::DrawMode::fill(), bounds, color);
-                n += 1;
-            }
+    /// Piece that holding would bring into play, if holding is allowed and
+    /// that piece is already known.
+    pub fn hold_swap_piece(&self) -> Option<Piece> {
+        if !self.can_hold {
+            return None;
         }
 
-        if n < 1 {
-            Ok(())
-        } else {
-            let mesh = mesh_builder.build(ctx).unwrap();
-            graphics::draw(ctx, &mesh, graphics::DrawParam::new())
+        self.hold.or_else(|| self.queue.front().copied())
+    }
+
+    /// Takes the piece at the front of the queue, topping the queue back up
+    /// to `preview_len` pieces.
+    pub fn pop_next_piece(&mut self) -> Piece {
+        while self.queue.len() <= self.preview_len {
+            let piece = self.randomizer.next_piece(&mut self.rng);
+            self.queue.push_back(piece);
         }
+
+        self.queue.pop_front().unwrap()
+    }
+
+    /// The next `preview_len` pieces, in the order they will spawn.
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn preview(&self) -> impl Iterator<Item = &Piece> {
+        self.queue.iter()
+    }
+
+    pub fn propagate_lines(&mut self) {
+        self.score += 100 * clear_lines(&mut self.board) as u32;
     }
 
     pub fn calc_drop_pos(&self, mut pos: [i32; 2], piece: &Piece) -> [i32; 2] {
         loop {
             pos[1] += 1;
-            if !self.is_valid_move(pos, &piece) {
+            if !self.is_valid_move(pos, piece) {
                 pos[1] -= 1;
                 break;
             }
@@ -270,37 +311,80 @@ impl TetrisState {
     pub fn update_ghost_pos(&mut self) {
         self.ghost_pos = self.calc_drop_pos(self.pos, &self.current_piece);
     }
+}
+
+/// Removes every completed row, shifting the rows above it down, and returns
+/// how many were removed.
+pub fn clear_lines(board: &mut TetrisBoard) -> usize {
+    let mut cleared = 0;
+    for y in 0..BOARD_HEIGHT {
+        if board[y].iter().all(|&v| v != PieceColor::Empty) {
+            cleared += 1;
+            board[y] = [PieceColor::Empty; BOARD_WIDTH];
+            board[0..=y].rotate_right(1);
+        }
+    }
+    cleared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_state(kind: PieceKind, pos: [i32; 2]) -> TetrisState {
+        let mut state = TetrisState::new(Box::new(Uniform), 0, 0);
+        state.current_piece = Piece::new(kind);
+        state.pos = pos;
+        state
+    }
 
-    pub fn fill_screen(&self, ctx: &mut Context, color: Color) -> GameResult<()> {
-        let rect = graphics::Rect::new(
-            0.0,
-            0.0,
-            UNIT * BOARD_WIDTH as f32,
-            UNIT * BOARD_HEIGHT as f32,
-        );
-        let mesh =
-            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), rect, color).unwrap();
-        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    fn fill(state: &mut TetrisState, cells: &[(usize, usize)]) {
+        for &(x, y) in cells {
+            state.board[y][x] = PieceColor::Blue;
+        }
+    }
+
+    #[test]
+    fn t_spin_triple_uses_last_kick() {
+        let mut state = empty_state(PieceKind::T, [4, 15]);
+
+        // Bottom three rows full except for a T-shaped slot, under an
+        // overhang that blocks the earlier kicks
+        let slot = [(4, 17), (4, 18), (4, 19), (5, 18)];
+        for y in 17..BOARD_HEIGHT {
+            for x in 0..BOARD_WIDTH {
+                if !slot.contains(&(x, y)) {
+                    fill(&mut state, &[(x, y)]);
+                }
+            }
+        }
+        fill(&mut state, &[(4, 15)]);
+
+        let (piece, pos) = state
+            .try_input(&state.current_piece, state.pos, Input::RotateCw)
+            .expect("Rotation should kick into the slot");
+        assert_eq!(piece.orientation, Orientation::Right);
+        // Fifth test of 0->R, (-1, -2) in the guideline's coordinates
+        assert_eq!(pos, [3, 17]);
+
+        state.current_piece = piece;
+        state.pos = pos;
+        state.lock_current_piece();
+        assert_eq!(clear_lines(&mut state.board), 3);
     }
 
-    pub fn draw_centered(&self, ctx: &mut Context, text: &str, color: Color) -> GameResult<()> {
-        let mut score_text = graphics::Text::new(text);
-        score_text.set_font(graphics::Font::default(), graphics::Scale::uniform(36.0));
-
-        let text_width = score_text.width(ctx) as f32;
-        let screen_width = UNIT * BOARD_WIDTH as f32;
-        let width_padding = (screen_width - text_width) / 2.0;
-
-        let text_height = score_text.height(ctx) as f32;
-        let screen_height = UNIT * BOARD_HEIGHT as f32;
-        let height_padding = (screen_height - text_height) / 2.0;
-
-        graphics::draw(
-            ctx,
-            &score_text,
-            graphics::DrawParam::new()
-                .color(color)
-                .dest([width_padding, height_padding]),
-        )
+    #[test]
+    fn i_kicks_off_right_wall() {
+        let mut state = empty_state(PieceKind::I, [7, 10]);
+        state.current_piece = state.current_piece.with_orientation(Orientation::Right);
+        // Vertical against the right wall
+        assert_eq!(state.current_piece.cells(state.pos)[0], (9, 10));
+
+        let (piece, pos) = state
+            .try_input(&state.current_piece, state.pos, Input::RotateCw)
+            .expect("Rotation should kick off the wall");
+        assert_eq!(piece.orientation, Orientation::Flipped);
+        // Second test of R->2, (-1, 0)
+        assert_eq!(pos, [6, 10]);
     }
 }
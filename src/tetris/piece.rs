@@ -1,69 +1,187 @@
-use crate::*;
+use serde::{Deserialize, Serialize};
 
-pub const PIECE_SIZE: f32 = 30.0;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PieceKind {
+    I,
+    J,
+    L,
+    O,
+    S,
+    T,
+    Z,
+}
+
+impl PieceKind {
+    pub fn from_char(c: char) -> Option<PieceKind> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(PieceKind::I),
+            'J' => Some(PieceKind::J),
+            'L' => Some(PieceKind::L),
+            'O' => Some(PieceKind::O),
+            'S' => Some(PieceKind::S),
+            'T' => Some(PieceKind::T),
+            'Z' => Some(PieceKind::Z),
+            _ => None,
+        }
+    }
+}
+
+pub const PIECE_KINDS: [PieceKind; 7] = [
+    PieceKind::I,
+    PieceKind::J,
+    PieceKind::L,
+    PieceKind::O,
+    PieceKind::S,
+    PieceKind::T,
+    PieceKind::Z,
+];
+
+/// The four SRS orientation states: spawn (0), right (R), flipped (2) and
+/// left (L).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Orientation {
+    Spawn,
+    Right,
+    Flipped,
+    Left,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Clockwise,
+    CounterClockwise,
+    Half,
+}
+
+pub const ORIENTATIONS: [Orientation; 4] = [
+    Orientation::Spawn,
+    Orientation::Right,
+    Orientation::Flipped,
+    Orientation::Left,
+];
+
+impl Rotation {
+    // Equivalent number of clockwise quarter turns
+    pub fn turns(self) -> usize {
+        match self {
+            Rotation::Clockwise => 1,
+            Rotation::Half => 2,
+            Rotation::CounterClockwise => 3,
+        }
+    }
+}
+
+impl Orientation {
+    pub fn rotated(self, rotation: Rotation) -> Orientation {
+        ORIENTATIONS[(self as usize + rotation.turns()) % 4]
+    }
+}
+
+// SRS wall kicks for clockwise rotations out of each orientation, written as
+// in the guideline with +y pointing up. Counter-clockwise kicks out of a state
+// are the negated clockwise kicks into it.
+const JLSTZ_KICKS: [[(i8, i8); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+
+const I_KICKS: [[(i8, i8); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+
+// The guideline does not define 180° rotations, these are the kicks
+// popularised by TETR.IO and are shared by all pieces.
+const HALF_KICKS: [[(i8, i8); 6]; 4] = [
+    [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    [(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    [(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    [(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Piece {
+    pub kind: PieceKind,
     pub color: PieceColor,
+    pub orientation: Orientation,
     pub shape: [(i8, i8); 4],
-    pub center: (f32, f32),
 }
 
 impl Piece {
-    pub fn random(rng: &mut SmallRng) -> Piece {
-        *PIECES.choose(rng).unwrap()
+    pub fn new(kind: PieceKind) -> Piece {
+        PIECES[kind as usize]
     }
 
-    pub fn rotate(&mut self) {
-        let (cx, cy) = self.center;
+    // Side length of the bounding box the piece rotates within
+    fn box_size(&self) -> i8 {
+        match self.kind {
+            PieceKind::I => 4,
+            _ => 3,
+        }
+    }
 
-        for xy in self.shape.iter_mut() {
-            let mut x = xy.0 as f32 - cx;
-            let mut y = xy.1 as f32 - cy;
+    pub fn rotated(mut self, rotation: Rotation) -> Piece {
+        self.orientation = self.orientation.rotated(rotation);
 
-            let tmp = x;
-            x = -y;
-            y = tmp;
+        // The O piece occupies the same cells in every orientation
+        if self.kind == PieceKind::O {
+            return self;
+        }
 
-            xy.0 = (x + cx) as i8;
-            xy.1 = (y + cy) as i8;
+        let n = self.box_size();
+        for _ in 0..rotation.turns() {
+            for xy in self.shape.iter_mut() {
+                *xy = (n - 1 - xy.1, xy.0);
+            }
         }
+
+        self
     }
 
-    pub fn x_bounds(&self) -> (i32, i32) {
-        let xmin = self.shape.iter().map(|&(x, _y)| x).min().unwrap();
-        let xmax = self.shape.iter().map(|&(x, _y)| x).max().unwrap();
-        (xmin as i32, xmax as i32)
+    pub fn with_orientation(self, orientation: Orientation) -> Piece {
+        let mut piece = self;
+        while piece.orientation != orientation {
+            piece = piece.rotated(Rotation::Clockwise);
+        }
+        piece
     }
 
-    pub fn gen_mesh(&self, ctx: &mut Context) -> graphics::Mesh {
-        let color = self.color.to_color();
+    /// Offsets to try, in order, when rotating the piece. These are in board
+    /// coordinates, so +y points down.
+    pub fn kicks(&self, rotation: Rotation) -> impl Iterator<Item = (i32, i32)> {
+        let from = self.orientation as usize;
+        let to = self.orientation.rotated(rotation) as usize;
 
-        let mut mesh_builder = graphics::MeshBuilder::new();
-        for &(x, y) in &self.shape {
-            const UNIT: f32 = PIECE_SIZE;
-            let bounds =
-                graphics::Rect::new(UNIT * x as f32, UNIT * y as f32, UNIT - 1.0, UNIT - 1.0);
-            mesh_builder.rectangle(graphics::DrawMode::fill(), bounds, color);
-        }
+        let (table, sign): (&'static [(i8, i8)], i32) = match (self.kind, rotation) {
+            (PieceKind::O, _) => (&[(0, 0)], 1),
+            (_, Rotation::Half) => (&HALF_KICKS[from], 1),
+            (PieceKind::I, Rotation::Clockwise) => (&I_KICKS[from], 1),
+            (PieceKind::I, Rotation::CounterClockwise) => (&I_KICKS[to], -1),
+            (_, Rotation::Clockwise) => (&JLSTZ_KICKS[from], 1),
+            (_, Rotation::CounterClockwise) => (&JLSTZ_KICKS[to], -1),
+        };
 
-        mesh_builder.build(ctx).unwrap()
+        table
+            .iter()
+            .map(move |&(x, y)| (sign * x as i32, -sign * y as i32))
     }
 
-    pub fn draw(&self, ctx: &mut Context, pos: [i32; 2], alpha: f32) -> GameResult<()> {
-        let mesh = self.gen_mesh(ctx);
-        let [x, y] = pos;
-        graphics::draw(
-            ctx,
-            &mesh,
-            graphics::DrawParam::new()
-                .dest([PIECE_SIZE * x as f32, PIECE_SIZE * y as f32])
-                .color(Color::new(1.0, 1.0, 1.0, alpha)),
-        )
+    /// Board coordinates covered by the piece when placed at `pos`.
+    pub fn cells(&self, pos: [i32; 2]) -> [(i32, i32); 4] {
+        let [px, py] = pos;
+        let mut cells = [(0, 0); 4];
+        for (cell, &(x, y)) in cells.iter_mut().zip(&self.shape) {
+            *cell = (px + x as i32, py + y as i32);
+        }
+        cells
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PieceColor {
     Teal,
     Blue,
@@ -75,55 +193,112 @@ pub enum PieceColor {
     Empty,
 }
 
-impl PieceColor {
-    pub fn to_color(&self) -> Color {
-        match self {
-            PieceColor::Teal => Color::from_rgb_u32(0x008080),
-            PieceColor::Blue => Color::from_rgb_u32(0x0341AE),
-            PieceColor::Orange => Color::from_rgb_u32(0xFF971C),
-            PieceColor::Yellow => Color::from_rgb_u32(0xFFD500),
-            PieceColor::Green => Color::from_rgb_u32(0x72CB3B),
-            PieceColor::Purple => Color::from_rgb_u32(0x800080),
-            PieceColor::Red => Color::from_rgb_u32(0xFF3213),
-            PieceColor::Empty => panic!(),
-        }
-    }
-}
-
+// Spawn orientations, as laid out by the Super Rotation System. Indexed by
+// `PieceKind`.
 pub const PIECES: [Piece; 7] = [
     Piece {
+        kind: PieceKind::I,
         color: PieceColor::Teal,
-        shape: [(0, 0), (1, 0), (2, 0), (3, 0)],
-        center: (2.0, 1.0),
+        orientation: Orientation::Spawn,
+        shape: [(0, 1), (1, 1), (2, 1), (3, 1)],
     },
     Piece {
+        kind: PieceKind::J,
         color: PieceColor::Blue,
+        orientation: Orientation::Spawn,
         shape: [(0, 0), (0, 1), (1, 1), (2, 1)],
-        center: (1.0, 1.0),
     },
     Piece {
+        kind: PieceKind::L,
         color: PieceColor::Orange,
+        orientation: Orientation::Spawn,
         shape: [(2, 0), (0, 1), (1, 1), (2, 1)],
-        center: (2.0, 1.0),
     },
     Piece {
+        kind: PieceKind::O,
         color: PieceColor::Yellow,
-        shape: [(0, 0), (1, 0), (0, 1), (1, 1)],
-        center: (1.0, 1.0),
+        orientation: Orientation::Spawn,
+        shape: [(1, 0), (2, 0), (1, 1), (2, 1)],
     },
     Piece {
+        kind: PieceKind::S,
         color: PieceColor::Green,
+        orientation: Orientation::Spawn,
         shape: [(1, 0), (2, 0), (0, 1), (1, 1)],
-        center: (1.0, 1.0),
     },
     Piece {
+        kind: PieceKind::T,
         color: PieceColor::Purple,
+        orientation: Orientation::Spawn,
         shape: [(1, 0), (0, 1), (1, 1), (2, 1)],
-        center: (1.0, 1.0),
     },
     Piece {
+        kind: PieceKind::Z,
         color: PieceColor::Red,
+        orientation: Orientation::Spawn,
         shape: [(0, 0), (1, 0), (1, 1), (2, 1)],
-        center: (1.0, 1.0),
     },
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Kicks in board coordinates, from the guideline tables with y flipped
+    fn kicks(kind: PieceKind, from: Orientation, rotation: Rotation) -> Vec<(i32, i32)> {
+        Piece::new(kind)
+            .with_orientation(from)
+            .kicks(rotation)
+            .collect()
+    }
+
+    #[test]
+    fn jlstz_kicks_match_guideline() {
+        use Orientation::*;
+
+        let cw = Rotation::Clockwise;
+        let ccw = Rotation::CounterClockwise;
+        // 0->R, R->0, R->2, 2->L, L->0 and 0->L
+        let cases = [
+            (Spawn, cw, [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]),
+            (Right, ccw, [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]),
+            (Right, cw, [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]),
+            (Flipped, cw, [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]),
+            (Left, cw, [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]),
+            (Spawn, ccw, [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]),
+        ];
+
+        for &kind in &[
+            PieceKind::J,
+            PieceKind::L,
+            PieceKind::S,
+            PieceKind::T,
+            PieceKind::Z,
+        ] {
+            for (from, rotation, expected) in &cases {
+                assert_eq!(kicks(kind, *from, *rotation), expected.to_vec());
+            }
+        }
+    }
+
+    #[test]
+    fn i_kicks_match_guideline() {
+        use Orientation::*;
+
+        let cw = Rotation::Clockwise;
+        let ccw = Rotation::CounterClockwise;
+        // 0->R, R->0, R->2, 2->L, L->0 and 0->L
+        let cases = [
+            (Spawn, cw, [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]),
+            (Right, ccw, [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)]),
+            (Right, cw, [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)]),
+            (Flipped, cw, [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)]),
+            (Left, cw, [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)]),
+            (Spawn, ccw, [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)]),
+        ];
+
+        for (from, rotation, expected) in &cases {
+            assert_eq!(kicks(PieceKind::I, *from, *rotation), expected.to_vec());
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::*;
+
+use std::collections::HashSet;
+
+/// Final resting place of the current piece, along with the inputs that
+/// take it there from its current position. Placements that swap in the
+/// held piece first have `hold` set and start with `Input::Hold`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub kind: PieceKind,
+    pub hold: bool,
+    pub rotation: Orientation,
+    pub x: i32,
+    pub y: i32,
+    pub path: Vec<Input>,
+}
+
+impl Placement {
+    /// The piece as it sits once placed.
+    pub fn piece(&self) -> Piece {
+        Piece::new(self.kind).with_orientation(self.rotation)
+    }
+}
+
+/// What happened when a placement locked, handed to evaluators along with
+/// the board it left behind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacementInfo {
+    pub lines_cleared: usize,
+    // Cells of the placed piece that went away with the cleared lines
+    pub eroded_cells: usize,
+    // Row the middle of the piece landed on, before any clears, counting
+    // the bottom row as one
+    pub landing_height: f64,
+}
+
+// Inputs explored when searching for placements. Soft drops are left out, so
+// only positions reachable from above are considered.
+const SEARCH_INPUTS: [Input; 5] = [
+    Input::Left,
+    Input::Right,
+    Input::RotateCw,
+    Input::RotateCcw,
+    Input::Rotate180,
+];
+
+impl TetrisState {
+    /// Enumerates every placement reachable by rotating (with kicks) and
+    /// shifting the current piece, then hard dropping it. Each placement
+    /// comes with a shortest input path, and placements that would leave
+    /// identical boards behind are only listed once.
+    pub fn placements(&self) -> Vec<Placement> {
+        let mut placements = self.placements_for(&self.current_piece, self.pos);
+
+        if let Some(piece) = self.hold_swap_piece() {
+            if piece.kind != self.current_piece.kind {
+                let held = self.placements_for(&piece, SPAWN_POS);
+                placements.extend(held.into_iter().map(|mut placement| {
+                    placement.hold = true;
+                    placement.path.insert(0, Input::Hold);
+                    placement
+                }));
+            }
+        }
+
+        placements
+    }
+
+    /// Same as `placements`, but for any piece starting out at `pos`. Use
+    /// `SPAWN_POS` to plan ahead for pieces in the preview.
+    pub fn placements_for(&self, piece: &Piece, pos: [i32; 2]) -> Vec<Placement> {
+        let mut placements = Vec::new();
+        let mut seen = Vec::new();
+
+        let start = (*piece, pos);
+        let mut visited = HashSet::new();
+        visited.insert((start.0.orientation, start.1));
+
+        let mut queue = VecDeque::new();
+        queue.push_back((start.0, start.1, Vec::new()));
+
+        while let Some((piece, pos, path)) = queue.pop_front() {
+            let [x, y] = self.calc_drop_pos(pos, &piece);
+            let mut cells = piece.cells([x, y]);
+            cells.sort_unstable();
+
+            if !seen.contains(&cells) {
+                seen.push(cells);
+
+                let mut path = path.clone();
+                path.push(Input::Drop);
+                placements.push(Placement {
+                    kind: piece.kind,
+                    hold: false,
+                    rotation: piece.orientation,
+                    x,
+                    y,
+                    path,
+                });
+            }
+
+            for &input in &SEARCH_INPUTS {
+                if let Some((next, next_pos)) = self.try_input(&piece, pos, input) {
+                    if visited.insert((next.orientation, next_pos)) {
+                        let mut path = path.clone();
+                        path.push(input);
+                        queue.push_back((next, next_pos, path));
+                    }
+                }
+            }
+        }
+
+        placements
+    }
+
+    /// Picks the placement with the lowest loss, as judged by `f` on the
+    /// board left behind after locking the piece and clearing lines. With a
+    /// `depth` above one, each placement is scored by the best line of play
+    /// over the following pieces in the preview, every line being played to
+    /// the same depth. The depth is capped by the length of the preview, and
+    /// placements that cannot be followed that far are skipped, such as a
+    /// hold into the empty slot when every piece in the preview is needed.
+    pub fn pick_placement<F>(&self, depth: usize, f: F) -> Option<Placement>
+    where
+        F: Fn(&TetrisBoard, &PlacementInfo) -> f64,
+    {
+        let depth = depth.clamp(1, self.queue.len() + 1);
+        let mut best = (f64::INFINITY, None);
+
+        for placement in self.placements() {
+            let loss = self.placement_loss(&placement, depth, &f);
+            if best.1.is_none() || loss < best.0 {
+                best = (loss, Some(placement));
+            }
+        }
+
+        best.1
+    }
+
+    fn placement_loss<F>(&self, placement: &Placement, depth: usize, f: &F) -> f64
+    where
+        F: Fn(&TetrisBoard, &PlacementInfo) -> f64,
+    {
+        if depth <= 1 {
+            let (board, info) = self.resolve_placement(placement);
+            return f(&board, &info);
+        }
+
+        match self.after_placement(placement) {
+            // Too few pieces left in the preview to follow this line as far
+            // as the others
+            None => f64::INFINITY,
+            Some(next) if next.is_over => f64::INFINITY,
+            Some(next) => next
+                .placements()
+                .iter()
+                .map(|placement| next.placement_loss(placement, depth - 1, f))
+                .fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    /// The board with the piece locked at `placement` and completed lines
+    /// cleared, the same way the game itself would clear them.
+    pub fn resolve_placement(&self, placement: &Placement) -> (TetrisBoard, PlacementInfo) {
+        let mut board = self.board;
+        let piece = placement.piece();
+        let cells = piece.cells([placement.x, placement.y]);
+        for &(x, y) in &cells {
+            board[y as usize][x as usize] = piece.color;
+        }
+
+        let eroded_cells = cells
+            .iter()
+            .filter(|&&(_, y)| board[y as usize].iter().all(|&v| v != PieceColor::Empty))
+            .count();
+
+        let (top, bottom) = cells
+            .iter()
+            .fold((i32::MAX, i32::MIN), |(top, bottom), &(_, y)| {
+                (top.min(y), bottom.max(y))
+            });
+        let landing_height = BOARD_HEIGHT as f64 - (top + bottom) as f64 / 2.0;
+
+        let lines_cleared = clear_lines(&mut board);
+
+        let info = PlacementInfo {
+            lines_cleared,
+            eroded_cells,
+            landing_height,
+        };
+
+        (board, info)
+    }
+
+    /// Copy of the state after `placement`, for planning ahead. The copy only
+    /// knows about pieces that were visible in the preview, so this returns
+    /// `None` when the piece spawning next is not known yet.
+    pub fn after_placement(&self, placement: &Placement) -> Option<TetrisState> {
+        // Holding with an empty hold slot also takes a piece from the queue
+        let consumed = if placement.hold && self.hold.is_none() {
+            2
+        } else {
+            1
+        };
+
+        if consumed > self.queue.len() {
+            return None;
+        }
+
+        // Shrink the preview first, so that nothing gets drawn from the
+        // randomizer to replace the pieces used up
+        let mut state = self.clone();
+        state.preview_len = self.queue.len() - consumed;
+        state.apply_placement(placement);
+
+        Some(state)
+    }
+
+    /// Copy of the state for planning ahead, where `kind` is assumed to be
+    /// the first piece past the end of the preview.
+    pub fn with_next_piece(&self, kind: PieceKind) -> TetrisState {
+        let mut state = self.clone();
+        state.randomizer.observe(kind);
+        state.queue.push_back(Piece::new(kind));
+        state.preview_len += 1;
+        state
+    }
+
+    /// Locks the current piece at `placement` in a single step, without
+    /// going through gravity or individual inputs.
+    pub fn apply_placement(&mut self, placement: &Placement) {
+        if self.is_over {
+            return;
+        }
+
+        self.recording.push(Event::Place {
+            hold: placement.hold,
+            rotation: placement.rotation,
+            x: placement.x,
+            y: placement.y,
+        });
+
+        if placement.hold {
+            self.hold_current_piece();
+        }
+
+        let piece = self.current_piece.with_orientation(placement.rotation);
+        let pos = [placement.x, placement.y];
+
+        if !self.is_valid_move(pos, &piece) {
+            self.is_over = true;
+            return;
+        }
+
+        self.current_piece = piece;
+        self.pos = pos;
+        self.lock_and_spawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_scored_to_the_same_depth() {
+        // With one piece in the preview and nothing held, holding uses up
+        // the preview and cannot be followed to a second piece
+        let state = TetrisState::new(Box::new(Uniform), 1, 0);
+        let f = |_: &TetrisBoard, _: &PlacementInfo| 0.0;
+
+        let placements = state.placements();
+        assert!(placements.iter().any(|placement| placement.hold));
+        for placement in &placements {
+            let loss = state.placement_loss(placement, 2, &f);
+            assert_eq!(loss.is_finite(), !placement.hold, "{:?}", placement);
+        }
+
+        let picked = state.pick_placement(5, f).unwrap();
+        assert!(!picked.hold);
+    }
+}
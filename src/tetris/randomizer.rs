@@ -0,0 +1,378 @@
+use crate::*;
+
+/// Source of the piece sequence. Randomizers draw from the state's `rng`, so
+/// that the sequence only depends on how that generator was seeded.
+pub trait Randomizer: Send + Sync {
+    fn next_piece(&mut self, rng: &mut SmallRng) -> Piece;
+
+    /// Probability of each kind, indexed by `PieceKind`, being dealt next.
+    fn distribution(&self) -> [f64; 7];
+
+    /// Updates the randomizer as if `kind` had just been dealt, so that
+    /// search can reason about hypothetical pieces.
+    fn observe(&mut self, kind: PieceKind);
+
+    /// Specification string that `parse_randomizer` turns back into an
+    /// equivalent randomizer.
+    fn name(&self) -> String;
+
+    /// Forgets any history, as at the start of a new game.
+    fn reset(&mut self);
+
+    fn box_clone(&self) -> Box<dyn Randomizer>;
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Box<dyn Randomizer> {
+        self.box_clone()
+    }
+}
+
+/// Every piece is equally likely, independently of previous ones.
+#[derive(Clone, Default)]
+pub struct Uniform;
+
+impl Randomizer for Uniform {
+    fn next_piece(&mut self, rng: &mut SmallRng) -> Piece {
+        *PIECES.choose(rng).unwrap()
+    }
+
+    fn distribution(&self) -> [f64; 7] {
+        [1.0 / 7.0; 7]
+    }
+
+    fn observe(&mut self, _kind: PieceKind) {}
+
+    fn name(&self) -> String {
+        "uniform".to_string()
+    }
+
+    fn reset(&mut self) {}
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Deals pieces from a shuffled bag holding `copies` of each piece, refilling
+/// it once empty. One copy gives the guideline 7-bag, two give a 14-bag.
+#[derive(Clone)]
+pub struct Bag {
+    copies: usize,
+    bag: Vec<PieceKind>,
+    // Bags refilled by `observe` are left in order until the next draw
+    shuffled: bool,
+}
+
+impl Bag {
+    pub fn new(copies: usize) -> Bag {
+        Bag {
+            copies,
+            bag: Vec::with_capacity(copies * PIECE_KINDS.len()),
+            shuffled: false,
+        }
+    }
+}
+
+impl Bag {
+    fn refill(&mut self) {
+        for _ in 0..self.copies {
+            self.bag.extend_from_slice(&PIECE_KINDS);
+        }
+        self.shuffled = false;
+    }
+}
+
+impl Randomizer for Bag {
+    fn next_piece(&mut self, rng: &mut SmallRng) -> Piece {
+        if self.bag.is_empty() {
+            self.refill();
+        }
+        if !self.shuffled {
+            self.bag.shuffle(rng);
+            self.shuffled = true;
+        }
+
+        Piece::new(self.bag.pop().unwrap())
+    }
+
+    fn distribution(&self) -> [f64; 7] {
+        if self.bag.is_empty() {
+            return [1.0 / 7.0; 7];
+        }
+
+        let mut distribution = [0.0; 7];
+        for &kind in &self.bag {
+            distribution[kind as usize] += 1.0 / self.bag.len() as f64;
+        }
+        distribution
+    }
+
+    fn observe(&mut self, kind: PieceKind) {
+        if self.bag.is_empty() {
+            self.refill();
+        }
+
+        if let Some(i) = self.bag.iter().position(|&k| k == kind) {
+            self.bag.swap_remove(i);
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{}-bag", self.copies * PIECE_KINDS.len())
+    }
+
+    fn reset(&mut self) {
+        self.bag.clear();
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// TGM-style randomizer: rerolls up to `rolls` times while the piece is among
+/// the last four dealt. The first piece is never S, Z or O.
+#[derive(Clone)]
+pub struct History {
+    rolls: usize,
+    history: [PieceKind; 4],
+    first: bool,
+}
+
+impl History {
+    // TGM starts out with a history of S and Z pieces
+    const INITIAL_HISTORY: [PieceKind; 4] =
+        [PieceKind::Z, PieceKind::S, PieceKind::S, PieceKind::Z];
+
+    pub fn new(rolls: usize) -> History {
+        History {
+            rolls,
+            history: History::INITIAL_HISTORY,
+            first: true,
+        }
+    }
+
+    pub fn tgm() -> History {
+        History::new(4)
+    }
+}
+
+impl Randomizer for History {
+    fn next_piece(&mut self, rng: &mut SmallRng) -> Piece {
+        let kind = if self.first {
+            *[PieceKind::I, PieceKind::J, PieceKind::L, PieceKind::T]
+                .choose(rng)
+                .unwrap()
+        } else {
+            let mut kind = *PIECE_KINDS.choose(rng).unwrap();
+            for _ in 1..self.rolls {
+                if !self.history.contains(&kind) {
+                    break;
+                }
+                kind = *PIECE_KINDS.choose(rng).unwrap();
+            }
+            kind
+        };
+
+        self.observe(kind);
+        Piece::new(kind)
+    }
+
+    fn distribution(&self) -> [f64; 7] {
+        if self.first {
+            let mut distribution = [0.0; 7];
+            for &kind in &[PieceKind::I, PieceKind::J, PieceKind::L, PieceKind::T] {
+                distribution[kind as usize] = 0.25;
+            }
+            return distribution;
+        }
+
+        // A piece from the history is only kept once every roll has hit the
+        // history, any other piece is kept on whichever roll first draws it
+        let in_history = PIECE_KINDS
+            .iter()
+            .filter(|kind| self.history.contains(kind))
+            .count() as f64
+            / 7.0;
+
+        let repeat = in_history.powi(self.rolls as i32 - 1) / 7.0;
+        let fresh = (0..self.rolls)
+            .map(|i| in_history.powi(i as i32))
+            .sum::<f64>()
+            / 7.0;
+
+        let mut distribution = [0.0; 7];
+        for &kind in &PIECE_KINDS {
+            distribution[kind as usize] = if self.history.contains(&kind) {
+                repeat
+            } else {
+                fresh
+            };
+        }
+        distribution
+    }
+
+    fn observe(&mut self, kind: PieceKind) {
+        self.first = false;
+        self.history.rotate_right(1);
+        self.history[0] = kind;
+    }
+
+    fn name(&self) -> String {
+        format!("history-{}", self.rolls)
+    }
+
+    fn reset(&mut self) {
+        self.history = History::INITIAL_HISTORY;
+        self.first = true;
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Replays a fixed sequence of pieces, starting over once it runs out.
+#[derive(Clone)]
+pub struct Fixed {
+    sequence: Vec<PieceKind>,
+    index: usize,
+}
+
+impl Fixed {
+    pub fn new(sequence: Vec<PieceKind>) -> Fixed {
+        assert!(!sequence.is_empty(), "Fixed sequence must not be empty");
+        Fixed { sequence, index: 0 }
+    }
+}
+
+impl Randomizer for Fixed {
+    fn next_piece(&mut self, _rng: &mut SmallRng) -> Piece {
+        let kind = self.sequence[self.index];
+        self.observe(kind);
+        Piece::new(kind)
+    }
+
+    fn distribution(&self) -> [f64; 7] {
+        let mut distribution = [0.0; 7];
+        distribution[self.sequence[self.index] as usize] = 1.0;
+        distribution
+    }
+
+    fn observe(&mut self, _kind: PieceKind) {
+        self.index = (self.index + 1) % self.sequence.len();
+    }
+
+    fn name(&self) -> String {
+        let sequence = self.sequence.iter().map(|kind| format!("{:?}", kind));
+        format!("fixed:{}", sequence.collect::<String>())
+    }
+
+    fn reset(&mut self) {
+        self.index = 0;
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Builds a randomizer from its name: `uniform`, `7-bag`, `14-bag` (or any
+/// multiple of seven), `history-N` for N rolls, `tgm` for `history-4`, or
+/// `fixed:SEQUENCE` with a sequence of piece letters such as `fixed:IOTSZJL`.
+pub fn parse_randomizer(name: &str) -> Option<Box<dyn Randomizer>> {
+    let name = name.to_lowercase();
+
+    if name == "uniform" {
+        return Some(Box::new(Uniform));
+    }
+
+    if name == "tgm" {
+        return Some(Box::new(History::tgm()));
+    }
+
+    if let Some(size) = name.strip_suffix("-bag") {
+        let size = size.parse::<usize>().ok()?;
+        if size == 0 || size % PIECE_KINDS.len() != 0 {
+            return None;
+        }
+        return Some(Box::new(Bag::new(size / PIECE_KINDS.len())));
+    }
+
+    if let Some(rolls) = name.strip_prefix("history-") {
+        let rolls = rolls.parse::<usize>().ok().filter(|&n| n > 0)?;
+        return Some(Box::new(History::new(rolls)));
+    }
+
+    if let Some(sequence) = name.strip_prefix("fixed:") {
+        let sequence = sequence
+            .chars()
+            .map(PieceKind::from_char)
+            .collect::<Option<Vec<_>>>()?;
+        if sequence.is_empty() {
+            return None;
+        }
+        return Some(Box::new(Fixed::new(sequence)));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 100_000;
+
+    // Checks the distribution of what `randomizer` deals next against the
+    // frequencies of many draws from copies of it
+    fn assert_distribution(randomizer: &dyn Randomizer) {
+        let distribution = randomizer.distribution();
+        let total = distribution.iter().sum::<f64>();
+        assert!((total - 1.0).abs() < 1e-9, "Sums to {}", total);
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut counts = [0; 7];
+        for _ in 0..SAMPLES {
+            let kind = randomizer.box_clone().next_piece(&mut rng).kind;
+            counts[kind as usize] += 1;
+        }
+
+        for (&count, &p) in counts.iter().zip(&distribution) {
+            let frequency = count as f64 / SAMPLES as f64;
+            assert!(
+                (frequency - p).abs() < 0.01,
+                "{}: expected {:?}, sampled {:?}",
+                randomizer.name(),
+                distribution,
+                counts
+            );
+        }
+    }
+
+    // `randomizer` after dealing `sequence`
+    fn after(mut randomizer: Box<dyn Randomizer>, sequence: &str) -> Box<dyn Randomizer> {
+        for kind in sequence.chars().map(|c| PieceKind::from_char(c).unwrap()) {
+            randomizer.observe(kind);
+        }
+        randomizer
+    }
+
+    #[test]
+    fn distributions_match_samples() {
+        for name in &[
+            "uniform",
+            "7-bag",
+            "14-bag",
+            "tgm",
+            "history-2",
+            "fixed:IOT",
+        ] {
+            let randomizer = parse_randomizer(name).unwrap();
+            assert_distribution(randomizer.as_ref());
+            assert_distribution(after(randomizer.clone(), "T").as_ref());
+            assert_distribution(after(randomizer.clone(), "IJJLT").as_ref());
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Version written into every replay file
+const FORMAT_VERSION: u32 = 1;
+
+/// A single change to a game, as made by players and agents. Applying the
+/// events of a game to a fresh state with the same seed and rules plays it
+/// out again exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Tick,
+    Input(Input),
+    // A placement applied in a single step
+    Place {
+        hold: bool,
+        rotation: Orientation,
+        x: i32,
+        y: i32,
+    },
+}
+
+impl Event {
+    pub fn apply(&self, state: &mut TetrisState) {
+        match *self {
+            Event::Tick => state.tick(),
+            Event::Input(input) => state.apply(input),
+            Event::Place {
+                hold,
+                rotation,
+                x,
+                y,
+            } => state.apply_placement(&Placement {
+                // Only the final position is needed to apply a placement
+                kind: state.current_piece.kind,
+                hold,
+                rotation,
+                x,
+                y,
+                path: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// Events of a game being recorded. Copies of a state never record, since
+/// they only exist to look ahead or to play other games on.
+#[derive(Debug, Default)]
+pub struct Recording(Option<Vec<Event>>);
+
+impl Clone for Recording {
+    fn clone(&self) -> Recording {
+        Recording(None)
+    }
+}
+
+impl Recording {
+    pub fn push(&mut self, event: Event) {
+        if let Some(events) = &mut self.0 {
+            events.push(event);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(events) = &mut self.0 {
+            events.clear();
+        }
+    }
+}
+
+// Games are only recorded from the gui
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+impl TetrisState {
+    /// Starts recording every event from here on.
+    pub fn record(&mut self) {
+        self.recording = Recording(Some(Vec::new()));
+    }
+
+    /// Events recorded since the start of the game, if recording.
+    pub fn events(&self) -> Option<&[Event]> {
+        self.recording.0.as_deref()
+    }
+}
+
+/// A recorded game, which can be played back without whoever played it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub ruleset: Ruleset,
+    pub seed: u64,
+    // Final score, to check the playback against
+    pub score: u32,
+    pub events: Vec<Event>,
+}
+
+impl Replay {
+    /// Replay of a game played under `ruleset`, or `None` if the game was
+    /// not recorded from its start.
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn new(ruleset: Ruleset, state: &TetrisState) -> Option<Replay> {
+        Some(Replay {
+            version: FORMAT_VERSION,
+            ruleset,
+            seed: state.seed,
+            score: state.score,
+            events: state.events()?.to_vec(),
+        })
+    }
+
+    /// Fresh game to play the events on, or `None` if this build cannot
+    /// play by the recorded rules.
+    pub fn new_state(&self) -> Option<TetrisState> {
+        self.ruleset.new_state(self.seed)
+    }
+
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        load_versioned(path, "replay", FORMAT_VERSION)
+    }
+}
@@ -0,0 +1,38 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Rules a game is played under. Saved agents record the rules they were
+/// trained for, since their weights only make sense under those rules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ruleset {
+    // Name understood by `parse_randomizer`
+    pub randomizer: String,
+    pub preview_len: usize,
+    pub board_width: usize,
+    pub board_height: usize,
+}
+
+impl Ruleset {
+    /// Rules with the given randomizer and preview, on the board this build
+    /// plays on.
+    pub fn new(randomizer: &str, preview_len: usize) -> Ruleset {
+        Ruleset {
+            randomizer: randomizer.to_string(),
+            preview_len,
+            board_width: BOARD_WIDTH,
+            board_height: BOARD_HEIGHT,
+        }
+    }
+
+    /// Fresh game under these rules, or `None` if this build cannot play by
+    /// them.
+    pub fn new_state(&self, seed: u64) -> Option<TetrisState> {
+        if (self.board_width, self.board_height) != (BOARD_WIDTH, BOARD_HEIGHT) {
+            return None;
+        }
+
+        let randomizer = parse_randomizer(&self.randomizer)?;
+        Some(TetrisState::new(randomizer, self.preview_len, seed))
+    }
+}